@@ -0,0 +1,278 @@
+//! Account storage keyed by the sha256 hash of the account ID.
+//!
+//! Keying `accounts` by the account ID itself meant that every key took up to 1 + 4 + 64 = 69 bytes (prefix, Borsh
+//! length and the longest possible account ID). With a hash, every key takes 1 + 32 = 33 bytes regardless of the
+//! account ID's length, which saves 36 bytes per account. With a 16-byte balance and the 40 bytes of per-entry overhead
+//! charged by the protocol, `bytes_for_longest_account_id` went down from 125 to 89 bytes. The record now also keeps the
+//! 16-byte storage deposit of the account, which brings it to 105 bytes.
+//!
+//! Contracts that were deployed with the old layout are upgraded with `migrate`. Their existing entries are still
+//! read from the old map and are moved to the new one the next time they're written, or in bulk with `migrate_accounts`.
+//! Until they're moved, they're reported as having paid the storage the old layout charged for an account.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, CryptoHash};
+
+use crate::*;
+
+/// The maximum number of accounts that can be migrated in a single call
+pub const MAX_MIGRATION_BATCH_SIZE: usize = 100;
+
+/// The record stored for every registered account
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct AccountRecord {
+    /// The account's balance
+    pub balance: Balance,
+    /// The $NEAR the account deposited for its storage. Anything above the current minimum storage balance can be withdrawn.
+    pub storage_paid: Balance,
+}
+
+/// Returns the key of an account in the `accounts` map. This is the sha256 hash of the account ID.
+pub(crate) fn account_key(account_id: &AccountId) -> CryptoHash {
+    env::sha256_array(account_id.as_bytes())
+}
+
+/// The state layout of contracts deployed before account keys were hashed
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldContract {
+    pub accounts: LookupMap<AccountId, Balance>,
+    pub total_supply: Balance,
+    pub bytes_for_longest_account_id: StorageUsage,
+    pub metadata: LazyOption<FungibleTokenMetadata>,
+}
+
+impl Contract {
+    /// Internal method for getting the record of an account. Accounts that weren't migrated yet are read from the old map.
+    pub(crate) fn internal_get_account(&self, account_id: &AccountId) -> Option<AccountRecord> {
+        self.accounts.get(&account_key(account_id)).or_else(|| {
+            if self.legacy_accounts_migrated {
+                return None;
+            }
+            // Accounts registered before deposits were recorded paid exactly the minimum of the old layout
+            self.legacy_accounts.get(account_id).map(|balance| AccountRecord {
+                balance,
+                storage_paid: self.legacy_storage_paid,
+            })
+        })
+    }
+
+    /// Internal method for writing the record of an account. If the account was still in the old map, it's moved to the new one.
+    pub(crate) fn internal_set_account(&mut self, account_id: &AccountId, record: &AccountRecord) {
+        self.accounts.insert(&account_key(account_id), record);
+        if self.legacy_accounts_migrated {
+            return;
+        }
+        if let Some(legacy_balance) = self.legacy_accounts.remove(account_id) {
+            self.legacy_accounts_remaining = self.legacy_accounts_remaining.saturating_sub(1);
+            self.legacy_balance_remaining = self.legacy_balance_remaining.saturating_sub(legacy_balance);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Upgrades the state of a contract deployed before account keys were hashed. This can only be called by the
+    /// contract account itself, right after deploying the new code.
+    ///
+    /// Existing balances stay in the old map until they're written or moved with `migrate_accounts`.
+    /// `legacy_account_count` must be the number of accounts registered in the old map, so that the migration can
+    /// only be finished once all of them were moved. The count isn't trusted on its own: the old total supply is the
+    /// sum of the old balances, so the migration also can't be finished while any of it is still in the old map.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(owner_id: AccountId, legacy_account_count: u64) -> Self {
+        let old: OldContract = env::state_read().unwrap_or_else(|| env::panic_str("No state to migrate"));
+        let mut this = Self::internal_init_state(owner_id, old.total_supply, old.metadata.get().unwrap(), false, false);
+        // The old entries are still under the `Accounts` prefix, so keep reading them until they've all been moved
+        this.legacy_accounts_migrated = false;
+        this.legacy_accounts_remaining = legacy_account_count;
+        this.legacy_balance_remaining = old.total_supply;
+        // The old contract charged every account the storage of the longest account ID in its own layout
+        this.legacy_storage_paid = Balance::from(old.bytes_for_longest_account_id) * env::storage_byte_cost();
+        this
+    }
+
+    /// Moves the given accounts from the old map to the new one. Accounts that were already moved are skipped.
+    /// Only the owner can call this method.
+    pub fn migrate_accounts(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        require!(!self.legacy_accounts_migrated, "All accounts were already migrated");
+        require!(
+            account_ids.len() <= MAX_MIGRATION_BATCH_SIZE,
+            format!("Can't migrate more than {} accounts at once", MAX_MIGRATION_BATCH_SIZE)
+        );
+
+        for account_id in account_ids {
+            if self.legacy_accounts.contains_key(&account_id) {
+                let account = self.internal_unwrap_account(&account_id);
+                self.internal_set_account(&account_id, &account);
+            }
+        }
+    }
+
+    /// Marks the migration as finished so that the old map is no longer read. This can only be called once every
+    /// account was moved, either by being written or with `migrate_accounts`. Only the owner can call this method.
+    ///
+    /// Both the account count passed to `migrate` and the balances left in the old map must have reached 0. An
+    /// undercounted account with a balance still blocks the migration. An undercounted account with a zero balance
+    /// can't be detected and has to register again once the migration is finished.
+    pub fn finish_accounts_migration(&mut self) {
+        self.assert_owner();
+        require!(
+            self.legacy_accounts_remaining == 0,
+            format!("{} accounts still need to be migrated", self.legacy_accounts_remaining)
+        );
+        require!(
+            self.legacy_balance_remaining == 0,
+            format!("{} tokens are still held by accounts that need to be migrated", self.legacy_balance_remaining)
+        );
+        self.legacy_accounts_migrated = true;
+    }
+
+    /// Returns how many accounts still need to be moved from the old map before the migration can be finished
+    pub fn ft_legacy_accounts_remaining(&self) -> U64 {
+        U64(self.legacy_accounts_remaining)
+    }
+
+    /// Returns the sum of the balances that still need to be moved from the old map before the migration can be finished
+    pub fn ft_legacy_balance_remaining(&self) -> U128 {
+        U128(self.legacy_balance_remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::ft_core::FungibleTokenCore;
+
+    fn owner() -> AccountId {
+        AccountId::new_unchecked("owner.near".to_string())
+    }
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice.near".to_string())
+    }
+
+    fn bob() -> AccountId {
+        AccountId::new_unchecked("bob.near".to_string())
+    }
+
+    fn metadata() -> FungibleTokenMetadata {
+        near_sdk::serde_json::from_str(r#"{"spec":"ft-1.0.0","name":"Token","symbol":"TKN","decimals":24}"#).unwrap()
+    }
+
+    // Sets up the context of a call to the contract from the given account
+    fn set_predecessor(predecessor_id: AccountId) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(AccountId::new_unchecked("ft.near".to_string()))
+            .predecessor_account_id(predecessor_id)
+            .build());
+    }
+
+    // The first call is made by the contract itself so that `#[private]` methods pass
+    fn setup() {
+        set_predecessor(AccountId::new_unchecked("ft.near".to_string()));
+    }
+
+    // Writes the state of a contract deployed before account keys were hashed, with the given balances
+    fn write_old_state(balances: &[(AccountId, Balance)]) -> StorageUsage {
+        let mut accounts = LookupMap::new(StorageKey::Accounts.try_to_vec().unwrap());
+        for (account_id, balance) in balances {
+            accounts.insert(account_id, balance);
+        }
+        let metadata = LazyOption::new(StorageKey::Metadata.try_to_vec().unwrap(), Some(&metadata()));
+        let bytes_for_longest_account_id = 125;
+        env::state_write(&OldContract {
+            accounts,
+            total_supply: balances.iter().map(|(_, balance)| balance).sum(),
+            bytes_for_longest_account_id,
+            metadata,
+        });
+        bytes_for_longest_account_id
+    }
+
+    #[test]
+    fn old_layout_takes_125_bytes_per_account() {
+        setup();
+        let mut accounts: LookupMap<AccountId, Balance> = LookupMap::new(StorageKey::Accounts.try_to_vec().unwrap());
+        let initial_storage_usage = env::storage_usage();
+        accounts.insert(&AccountId::new_unchecked("a".repeat(64)), &0);
+        assert_eq!(env::storage_usage() - initial_storage_usage, 125);
+    }
+
+    #[test]
+    fn hashed_layout_takes_105_bytes_per_account() {
+        setup();
+        let mut contract = Contract::new(owner(), U128(1_000), metadata(), None, None);
+        assert_eq!(contract.bytes_for_longest_account_id, 105);
+
+        // The key doesn't depend on the length of the account ID
+        let initial_storage_usage = env::storage_usage();
+        contract.internal_register_account(&alice(), 0);
+        assert_eq!(env::storage_usage() - initial_storage_usage, 105);
+    }
+
+    #[test]
+    fn migrate_moves_legacy_balances() {
+        setup();
+        let old_bytes = write_old_state(&[(alice(), 100), (bob(), 200)]);
+        let mut contract = Contract::migrate(owner(), 2);
+        set_predecessor(owner());
+
+        // Legacy accounts are still readable and report the storage they paid with the old layout
+        assert_eq!(contract.ft_balance_of(alice()), U128(100));
+        assert_eq!(contract.ft_balance_of(bob()), U128(200));
+        assert_eq!(contract.ft_total_supply(), U128(300));
+        let legacy_storage_paid = Balance::from(old_bytes) * env::storage_byte_cost();
+        assert_eq!(contract.internal_get_account(&alice()).unwrap().storage_paid, legacy_storage_paid);
+        assert_eq!(contract.ft_legacy_accounts_remaining(), U64(2));
+
+        // Moving an account keeps its balance and takes it out of the old map
+        contract.migrate_accounts(vec![alice(), alice()]);
+        assert!(!contract.legacy_accounts.contains_key(&alice()));
+        assert_eq!(contract.accounts.get(&account_key(&alice())).unwrap().balance, 100);
+        assert_eq!(contract.ft_balance_of(alice()), U128(100));
+        assert_eq!(contract.ft_legacy_accounts_remaining(), U64(1));
+
+        // Writing an account moves it as well
+        contract.internal_transfer(&bob(), &alice(), 50, None);
+        assert!(!contract.legacy_accounts.contains_key(&bob()));
+        assert_eq!(contract.ft_balance_of(alice()), U128(150));
+        assert_eq!(contract.ft_balance_of(bob()), U128(150));
+        assert_eq!(contract.internal_get_account(&bob()).unwrap().storage_paid, legacy_storage_paid);
+        assert_eq!(contract.ft_legacy_accounts_remaining(), U64(0));
+
+        contract.finish_accounts_migration();
+        assert!(contract.legacy_accounts_migrated);
+        assert_eq!(contract.ft_balance_of(bob()), U128(150));
+    }
+
+    #[test]
+    #[should_panic(expected = "1 accounts still need to be migrated")]
+    fn finish_migration_requires_every_account_moved() {
+        setup();
+        write_old_state(&[(alice(), 100), (bob(), 200)]);
+        let mut contract = Contract::migrate(owner(), 2);
+        set_predecessor(owner());
+        contract.migrate_accounts(vec![alice()]);
+        contract.finish_accounts_migration();
+    }
+
+    #[test]
+    #[should_panic(expected = "200 tokens are still held by accounts that need to be migrated")]
+    fn finish_migration_rejects_an_undercounted_account() {
+        setup();
+        write_old_state(&[(alice(), 100), (bob(), 200)]);
+        // The owner passed a count that misses bob
+        let mut contract = Contract::migrate(owner(), 1);
+        set_predecessor(owner());
+        contract.migrate_accounts(vec![alice()]);
+        assert_eq!(contract.ft_legacy_accounts_remaining(), U64(0));
+        assert_eq!(contract.ft_legacy_balance_remaining(), U128(200));
+        contract.finish_accounts_migration();
+    }
+}
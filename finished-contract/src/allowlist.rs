@@ -0,0 +1,165 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, Promise};
+
+use crate::*;
+
+/// The maximum number of accounts that can be added to or removed from the allowlist in a single call
+pub const MAX_ALLOWLIST_BATCH_SIZE: usize = 100;
+
+/// The state of a permissioned deployment. Only accounts on the allowlist can register and transfer tokens.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Permissioning {
+    /// The accounts that are approved to hold and move tokens
+    pub allowlist: UnorderedSet<AccountId>,
+    /// An account that can manage the allowlist alongside the owner
+    pub compliance_id: Option<AccountId>,
+}
+
+impl Contract {
+    /// Internal method for getting the permissioning state. If the contract isn't permissioned, panic.
+    fn internal_unwrap_permissioning(&mut self) -> &mut Permissioning {
+        self.permissioning
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("The contract is not permissioned"))
+    }
+
+    /// Internal method for ensuring that the predecessor is the owner or the compliance account.
+    fn assert_allowlist_manager(&self) {
+        let predecessor_id = env::predecessor_account_id();
+        let is_compliance = self
+            .permissioning
+            .as_ref()
+            .and_then(|permissioning| permissioning.compliance_id.as_ref())
+            == Some(&predecessor_id);
+        require!(
+            predecessor_id == self.owner_id || is_compliance,
+            "Only the owner or the compliance account can manage the allowlist"
+        );
+    }
+
+    /// Internal method for ensuring that an account is allowed to hold and move tokens. If the contract isn't
+    /// permissioned, every account is allowed and no storage is read.
+    pub(crate) fn assert_allowlisted(&self, account_id: &AccountId) {
+        if let Some(permissioning) = &self.permissioning {
+            if !permissioning.allowlist.contains(account_id) {
+                env::panic_str(format!("The account {} is not on the allowlist", account_id).as_str())
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account that can manage the allowlist alongside the owner. Only the owner can call this method.
+    pub fn set_compliance_id(&mut self, compliance_id: Option<AccountId>) {
+        self.assert_owner();
+        self.internal_unwrap_permissioning().compliance_id = compliance_id;
+    }
+
+    /// Adds accounts to the allowlist. Only the owner or the compliance account can call this method.
+    ///
+    /// The caller must attach enough $NEAR to cover the storage of the new entries. Any excess is refunded.
+    #[payable]
+    pub fn allowlist_add(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_allowlist_manager();
+        require!(
+            account_ids.len() <= MAX_ALLOWLIST_BATCH_SIZE,
+            format!("Can't update more than {} accounts at once", MAX_ALLOWLIST_BATCH_SIZE)
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        let permissioning = self.internal_unwrap_permissioning();
+        // Only keep the accounts that weren't on the allowlist yet so that the event reflects what changed
+        let added: Vec<AccountId> = account_ids
+            .into_iter()
+            .filter(|account_id| permissioning.allowlist.insert(account_id))
+            .collect();
+
+        // Charge the caller for the storage of the new entries and refund any excess $NEAR
+        self.internal_settle_storage_change(initial_storage_usage);
+
+        if !added.is_empty() {
+            FtAllowlistAdd {
+                account_ids: &added,
+                updated_by: &env::predecessor_account_id(),
+            }
+            .emit();
+        }
+    }
+
+    /// Removes accounts from the allowlist. Only the owner or the compliance account can call this method.
+    ///
+    /// The storage the entries took up is always refunded to the owner, never to the caller. Entries can be added by
+    /// either manager, so refunding the caller would let the compliance account collect deposits the owner paid.
+    ///
+    /// Removed accounts keep their balance but can't send or receive tokens until they're added again.
+    pub fn allowlist_remove(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_allowlist_manager();
+        require!(
+            account_ids.len() <= MAX_ALLOWLIST_BATCH_SIZE,
+            format!("Can't update more than {} accounts at once", MAX_ALLOWLIST_BATCH_SIZE)
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        let permissioning = self.internal_unwrap_permissioning();
+        // Only keep the accounts that were on the allowlist so that the event reflects what changed
+        let removed: Vec<AccountId> = account_ids
+            .into_iter()
+            .filter(|account_id| permissioning.allowlist.remove(account_id))
+            .collect();
+
+        // Refund the storage that was freed up to the owner
+        let refund = Balance::from(initial_storage_usage - env::storage_usage()) * env::storage_byte_cost();
+        if refund > 0 {
+            Promise::new(self.owner_id.clone()).transfer(refund);
+        }
+
+        if !removed.is_empty() {
+            FtAllowlistRemove {
+                account_ids: &removed,
+                updated_by: &env::predecessor_account_id(),
+            }
+            .emit();
+        }
+    }
+
+    /// Returns whether the contract only allows accounts on the allowlist to register and transfer tokens.
+    pub fn ft_is_permissioned(&self) -> bool {
+        self.permissioning.is_some()
+    }
+
+    /// Returns whether an account is allowed to hold and move tokens. Every account is allowed if the contract isn't permissioned.
+    pub fn ft_is_allowlisted(&self, account_id: AccountId) -> bool {
+        self.permissioning
+            .as_ref()
+            .is_none_or(|permissioning| permissioning.allowlist.contains(&account_id))
+    }
+
+    /// Returns the account that can manage the allowlist alongside the owner.
+    pub fn ft_compliance_id(&self) -> Option<AccountId> {
+        self.permissioning
+            .as_ref()
+            .and_then(|permissioning| permissioning.compliance_id.clone())
+    }
+
+    /// Returns paginated accounts on the allowlist. Returns an empty vector if the contract isn't permissioned.
+    pub fn ft_allowlist(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<AccountId> {
+        let permissioning = match &self.permissioning {
+            Some(permissioning) => permissioning,
+            None => return vec![],
+        };
+
+        // Where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        permissioning
+            .allowlist
+            .iter()
+            .skip(start as usize)
+            // Take the first "limit" elements. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            .collect()
+    }
+}
@@ -12,8 +12,14 @@
 //! These events can be logged by calling `.emit()` on them if a single event, or calling
 //! [`FtMint::emit_many`], [`FtTransfer::emit_many`],
 //! or [`FtBurn::emit_many`] respectively.
+//!
+//! Changes to the allowlist of a permissioned deployment are logged under the custom `ft_allowlist`
+//! standard as [`FtAllowlistAdd`] and [`FtAllowlistRemove`] events.
+//!
+//! When the owner replaces the metadata's reference, it's logged under the custom `ft_metadata` standard as an
+//! [`FtMetadataReferenceUpdate`] event.
 
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{Base64VecU8, U128};
 use near_sdk::AccountId;
 use near_sdk::serde::Serialize;
 
@@ -25,6 +31,8 @@ use near_sdk::env;
 #[serde(rename_all = "snake_case")]
 pub(crate) enum NearEvent<'a> {
     Nep141(Nep141Event<'a>),
+    FtAllowlist(FtAllowlistEvent<'a>),
+    FtMetadata(FtMetadataEvent<'a>),
 }
 
 impl<'a> NearEvent<'a> {
@@ -118,4 +126,106 @@ fn new_141<'a>(version: &'static str, event_kind: Nep141EventKind<'a>) -> NearEv
 
 fn new_141_v1(event_kind: Nep141EventKind) -> NearEvent {
     new_141("1.0.0", event_kind)
-}
\ No newline at end of file
+}
+
+/// Data to log when accounts are added to the allowlist. To log this event,
+/// call [`.emit()`](FtAllowlistAdd::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct FtAllowlistAdd<'a> {
+    pub account_ids: &'a [AccountId],
+    pub updated_by: &'a AccountId,
+}
+
+impl FtAllowlistAdd<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits an allowlist add event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`FtAllowlistAdd`] represents the data of each addition.
+    pub fn emit_many(data: &[FtAllowlistAdd<'_>]) {
+        new_allowlist_v1(FtAllowlistEventKind::AllowlistAdd(data)).emit()
+    }
+}
+
+/// Data to log when accounts are removed from the allowlist. To log this event,
+/// call [`.emit()`](FtAllowlistRemove::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct FtAllowlistRemove<'a> {
+    pub account_ids: &'a [AccountId],
+    pub updated_by: &'a AccountId,
+}
+
+impl FtAllowlistRemove<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits an allowlist remove event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`FtAllowlistRemove`] represents the data of each removal.
+    pub fn emit_many(data: &[FtAllowlistRemove<'_>]) {
+        new_allowlist_v1(FtAllowlistEventKind::AllowlistRemove(data)).emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct FtAllowlistEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: FtAllowlistEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum FtAllowlistEventKind<'a> {
+    AllowlistAdd(&'a [FtAllowlistAdd<'a>]),
+    AllowlistRemove(&'a [FtAllowlistRemove<'a>]),
+}
+
+fn new_allowlist_v1(event_kind: FtAllowlistEventKind) -> NearEvent {
+    NearEvent::FtAllowlist(FtAllowlistEvent { version: "1.0.0", event_kind })
+}
+
+/// Data to log when the owner replaces the metadata's `reference` and `reference_hash`. To log this event,
+/// call [`.emit()`](FtMetadataReferenceUpdate::emit).
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+pub struct FtMetadataReferenceUpdate<'a> {
+    pub reference: &'a str,
+    pub reference_hash: &'a Base64VecU8,
+    pub updated_by: &'a AccountId,
+}
+
+impl FtMetadataReferenceUpdate<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        NearEvent::FtMetadata(FtMetadataEvent {
+            version: "1.0.0",
+            event_kind: FtMetadataEventKind::MetadataReferenceUpdate(&[self]),
+        })
+        .emit()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct FtMetadataEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: FtMetadataEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum FtMetadataEventKind<'a> {
+    MetadataReferenceUpdate(&'a [FtMetadataReferenceUpdate<'a>]),
+}
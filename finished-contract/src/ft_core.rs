@@ -1,10 +1,7 @@
-use near_sdk::{Gas, ext_contract, PromiseOrValue, assert_one_yocto, PromiseResult};
+use near_sdk::{ext_contract, require, PromiseOrValue, PromiseResult};
 
 use crate::*;
 
-const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
-const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER.0);
-
 #[ext_contract(ext_ft_core)]
 pub trait FungibleTokenCore {
     /// Transfers positive `amount` of tokens from the `env::predecessor_account_id` to `receiver_id`.
@@ -13,10 +10,14 @@ pub trait FungibleTokenCore {
     /// Exactly 1 yoctoNEAR must be attached.
     /// See [the Security section](https://github.com/near/NEPs/issues/141#user-content-security) of the standard.
     ///
+    /// If the receiver isn't registered, the sender can attach at least `storage_balance_bounds().min` instead to
+    /// register the receiver as part of the transfer. Any excess is refunded to the sender.
+    ///
     /// Arguments:
     /// - `receiver_id` - the account ID of the receiver.
     /// - `amount` - the amount of tokens to transfer. Must be a positive number in decimal string representation.
-    /// - `memo` - an optional string field in a free form to associate a memo with this transfer.
+    /// - `memo` - an optional string field in a free form to associate a memo with this transfer. Its length is limited
+    ///   by `ft_message_limits`. A memo starting with `memo-v1:` must be a structured memo (see [`StructuredMemo`]).
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
 
     /// Transfers positive `amount` of tokens from the `env::predecessor_account_id` to `receiver_id` account. Then
@@ -34,12 +35,13 @@ pub trait FungibleTokenCore {
     /// Both accounts must be registered with the contract for transfer to succeed. (See #145)
     /// This method must to be able to accept attached deposits, and must not panic on attached deposit. Exactly 1 yoctoNEAR must be attached. See [the Security
     /// section](https://github.com/near/NEPs/issues/141#user-content-security) of the standard.
+    /// Like `ft_transfer`, an unregistered receiver can be registered by attaching at least `storage_balance_bounds().min`.
     ///
     /// Arguments:
     /// - `receiver_id` - the account ID of the receiver contract. This contract will be called.
     /// - `amount` - the amount of tokens to transfer. Must be a positive number in a decimal string representation.
-    /// - `memo` - an optional string field in a free form to associate a memo with this transfer.
-    /// - `msg` - a string message that will be passed to `ft_on_transfer` contract call.
+    /// - `memo` - an optional string field in a free form to associate a memo with this transfer. See `ft_transfer`.
+    /// - `msg` - a string message that will be passed to `ft_on_transfer` contract call. Its length is limited by `ft_message_limits`.
     ///
     /// Returns a promise which will result in the amount of tokens withdrawn from sender's account.
     fn ft_transfer_call(
@@ -61,10 +63,12 @@ pub trait FungibleTokenCore {
 impl FungibleTokenCore for Contract {
     #[payable]
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
-        // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
-        assert_one_yocto();
+        // Ensure the memo is valid before changing any state
+        self.assert_valid_memo(memo.as_deref());
         // The sender is the user who called the method
         let sender_id = env::predecessor_account_id();
+        // Assert that the user attached exactly 1 yoctoNEAR, or enough to register the receiver if they aren't registered yet
+        self.internal_handle_transfer_deposit(&sender_id, &receiver_id);
         // How many tokens the user wants to withdraw
         let amount: Balance = amount.into();
         // Transfer the tokens
@@ -79,25 +83,32 @@ impl FungibleTokenCore for Contract {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<U128> {
-        // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
-        assert_one_yocto();
+        // Ensure the memo and msg are valid before changing any state
+        self.assert_valid_memo(memo.as_deref());
+        self.assert_valid_msg(&msg);
         // The sender is the user who called the method
         let sender_id = env::predecessor_account_id();
+        // Assert that the user attached exactly 1 yoctoNEAR, or enough to register the receiver if they aren't registered yet
+        self.internal_handle_transfer_deposit(&sender_id, &receiver_id);
         // How many tokens the sender wants to transfer
         let amount: Balance = amount.into();
+        // Ensure there's enough gas left for the receiver's call and the callback
+        self.assert_enough_gas_for_transfer_calls(1);
         // Transfer the tokens
         self.internal_transfer(&sender_id, &receiver_id, amount, memo);
 
         // Initiating receiver's call and the callback
-        // Defaulting GAS weight to 1, no attached deposit, and static GAS equal to the GAS for ft transfer call.
+        // No attached deposit, static GAS equal to the configured GAS for ft_on_transfer, and a GAS weight of 1 so that it gets all of the unused GAS.
         ext_ft_receiver::ext(receiver_id.clone())
-            .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
+            .with_static_gas(self.gas_settings.ft_on_transfer)
+            .with_unused_gas_weight(1)
             .ft_on_transfer(sender_id.clone(), amount.into(), msg)
             // We then resolve the promise and call ft_resolve_transfer on our own contract
-            // Defaulting GAS weight to 1, no attached deposit, and static GAS equal to the GAS for resolve transfer
+            // No attached deposit, static GAS equal to the configured GAS for resolve transfer, and a GAS weight of 0 so that it gets exactly that amount.
             .then(
                 Self::ext(env::current_account_id())
-                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .with_static_gas(self.gas_settings.resolve_transfer)
+                    .with_unused_gas_weight(0)
                     .ft_resolve_transfer(&sender_id, receiver_id, amount.into()),
             )
             .into()
@@ -105,12 +116,15 @@ impl FungibleTokenCore for Contract {
 
     fn ft_total_supply(&self) -> U128 {
         // Return the total supply casted to a U128
-        self.total_supply.into()
+        self.internal_shares_to_amount(self.total_supply).into()
     }
 
     fn ft_balance_of(&self, account_id: AccountId) -> U128 {
         // Return the balance of the account casted to a U128
-        self.accounts.get(&account_id).unwrap_or(0).into()
+        self.internal_get_account(&account_id)
+            .map(|account| self.internal_shares_to_amount(account.balance))
+            .unwrap_or(0)
+            .into()
     }
 }
 
@@ -142,6 +156,22 @@ pub trait FungibleTokenReceiver {
     ) -> PromiseOrValue<U128>;
 }
 
+#[near_bindgen]
+impl Contract {
+    /// Returns the balances of several accounts at once, in the same order as `account_ids`. Accounts that don't
+    /// exist have a balance of `"0"`. At most `MAX_BATCH_VIEW_ACCOUNTS` accounts can be queried at once.
+    pub fn ft_balances_of(&self, account_ids: Vec<AccountId>) -> Vec<U128> {
+        require!(
+            account_ids.len() <= MAX_BATCH_VIEW_ACCOUNTS,
+            format!("Can't query more than {} accounts at once", MAX_BATCH_VIEW_ACCOUNTS)
+        );
+        account_ids
+            .into_iter()
+            .map(|account_id| self.ft_balance_of(account_id))
+            .collect()
+    }
+}
+
 #[near_bindgen]
 impl Contract {
     // Finalize an `ft_transfer_call` chain of cross-contract calls.
@@ -178,10 +208,23 @@ impl Contract {
         receiver_id: AccountId,
         amount: U128,
     ) -> U128 {
-        let amount: Balance = amount.into();
+        // The result of the `ft_on_transfer` call is the only promise result
+        self.internal_resolve_transfer(sender_id, &receiver_id, amount.into(), 0).into()
+    }
+}
 
+impl Contract {
+    /// Internal method for resolving a single `ft_on_transfer` call whose result is at `promise_index`. Refunds
+    /// the sender for the unused amount and returns how many tokens were actually used by the receiver.
+    pub(crate) fn internal_resolve_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        promise_index: u64,
+    ) -> Balance {
         // Get the unused amount from the `ft_on_transfer` call result.
-        let unused_amount = match env::promise_result(0) {
+        let unused_amount = match env::promise_result(promise_index) {
             PromiseResult::NotReady => env::abort(),
             // If the promise was successful, get the return value and cast it to a U128.
             PromiseResult::Successful(value) => {
@@ -200,23 +243,35 @@ impl Contract {
         // If there is some unused amount, we should refund the sender
         if unused_amount > 0 {
             // Get the receiver's balance. We can only refund the sender if the receiver has enough balance.
-            let receiver_balance = self.accounts.get(&receiver_id).unwrap_or(0);
+            let receiver_balance = self
+                .internal_get_account(receiver_id)
+                .map(|account| self.internal_shares_to_amount(account.balance))
+                .unwrap_or(0);
             if receiver_balance > 0 {
                 // The amount to refund is the smaller of the unused amount and the receiver's balance as we can only refund up to what the receiver currently has.
                 let refund_amount = std::cmp::min(receiver_balance, unused_amount);
                 
-                // Refund the sender for the unused amount.
-                self.internal_transfer(&receiver_id, &sender_id, refund_amount, Some("Refund".to_string()));
+                // Refund the sender for the unused amount. This skips the allowlist checks of `internal_transfer`
+                // so that the refund can't fail if either account was removed from the allowlist in the meantime.
+                self.internal_withdraw(receiver_id, refund_amount);
+                self.internal_deposit(sender_id, refund_amount);
+                self.internal_record_transfer(receiver_id, sender_id, refund_amount, Some("Refund"));
+                FtTransfer {
+                    old_owner_id: receiver_id,
+                    new_owner_id: sender_id,
+                    amount: &U128(refund_amount),
+                    memo: Some("Refund"),
+                }
+                .emit();
                 
                 // Return what was actually used (the amount sent - refund)
-                let used_amount = amount
+                return amount
                     .checked_sub(refund_amount)
                     .unwrap_or_else(|| env::panic_str("Total supply overflow"));
-                return used_amount.into();
             }
         }
 
         // If the unused amount is 0, return the original amount.
-        amount.into()
+        amount
     }
 }
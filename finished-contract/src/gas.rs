@@ -0,0 +1,71 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, Gas};
+
+use crate::*;
+
+/// The default minimum gas attached to the receiver's `ft_on_transfer` call
+pub const DEFAULT_GAS_FOR_FT_ON_TRANSFER: Gas = Gas(25_000_000_000_000);
+
+/// The default gas kept for the `ft_resolve_transfer` callback
+pub const DEFAULT_GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
+
+/// The lowest gas the owner can keep for `ft_resolve_transfer`. With less, refunds could run out of gas.
+pub const MIN_GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
+
+/// The most gas a single transaction can attach. The gas settings of a transfer call can't exceed this.
+pub const MAX_PREPAID_GAS: Gas = Gas(300_000_000_000_000);
+
+/// The gas budgets used for `ft_transfer_call` and its resolve callback.
+///
+/// The receiver's `ft_on_transfer` call gets at least `ft_on_transfer` gas plus all of the remaining prepaid gas,
+/// while exactly `resolve_transfer` gas is kept for `ft_resolve_transfer`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasSettings {
+    pub ft_on_transfer: Gas, // The minimum gas attached to the receiver's `ft_on_transfer` call
+    pub resolve_transfer: Gas, // The fixed gas kept for the `ft_resolve_transfer` callback
+}
+
+impl Default for GasSettings {
+    fn default() -> Self {
+        Self {
+            ft_on_transfer: DEFAULT_GAS_FOR_FT_ON_TRANSFER,
+            resolve_transfer: DEFAULT_GAS_FOR_RESOLVE_TRANSFER,
+        }
+    }
+}
+
+impl Contract {
+    /// Internal method for ensuring that enough gas is left to attach the static gas of `receiver_count` receiver calls
+    /// and their resolve callback.
+    pub(crate) fn assert_enough_gas_for_transfer_calls(&self, receiver_count: u64) {
+        let required_gas = (self.gas_settings.ft_on_transfer.0 + self.gas_settings.resolve_transfer.0) * receiver_count;
+        require!(
+            env::prepaid_gas() - env::used_gas() > Gas(required_gas),
+            format!("More gas is required. At least {} gas must be left for the transfer call", required_gas)
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the gas budgets used for `ft_transfer_call` and its resolve callback. Only the owner can call this method.
+    pub fn set_gas_settings(&mut self, gas_settings: GasSettings) {
+        self.assert_owner();
+        require!(
+            gas_settings.resolve_transfer >= MIN_GAS_FOR_RESOLVE_TRANSFER,
+            format!("The gas for ft_resolve_transfer must be at least {}", MIN_GAS_FOR_RESOLVE_TRANSFER.0)
+        );
+        require!(
+            gas_settings.ft_on_transfer.0 + gas_settings.resolve_transfer.0 < MAX_PREPAID_GAS.0,
+            format!("The gas for a transfer call must be less than {}", MAX_PREPAID_GAS.0)
+        );
+        self.gas_settings = gas_settings;
+    }
+
+    /// Returns the gas budgets used for `ft_transfer_call` and its resolve callback.
+    pub fn ft_gas_settings(&self) -> GasSettings {
+        self.gas_settings.clone()
+    }
+}
@@ -0,0 +1,409 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, StorageUsage};
+
+use crate::storage::StorageManagement;
+use crate::*;
+
+/// The maximum number of transfers that can be kept in a single account's history
+pub const MAX_TRANSFER_HISTORY_SIZE: u32 = 100;
+
+/// The maximum number of bytes of a memo that are kept in the history. Longer memos are truncated.
+pub const MAX_HISTORY_MEMO_LEN: usize = 64;
+
+/// Whether tokens left or entered the account for a given transfer
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDirection {
+    Incoming,
+    Outgoing,
+}
+
+/// A single transfer as it's stored in (and returned from) an account's history
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferRecord {
+    pub counterparty: AccountId, // The other side of the transfer (the receiver for outgoing transfers and the sender for incoming ones)
+    pub amount: U128, // The amount of tokens that were transferred
+    pub direction: TransferDirection, // Whether the tokens left or entered the account
+    pub memo: Option<String>, // The memo of the transfer, truncated to `MAX_HISTORY_MEMO_LEN` bytes
+    pub timestamp: U64, // The block timestamp (in nanoseconds) at which the transfer happened
+}
+
+impl TransferRecord {
+    /// Creates a new record stamped with the current block timestamp. The memo is truncated if it's too long.
+    pub(crate) fn new(
+        counterparty: &AccountId,
+        amount: Balance,
+        direction: TransferDirection,
+        memo: Option<&str>,
+    ) -> Self {
+        Self {
+            counterparty: counterparty.clone(),
+            amount: amount.into(),
+            direction,
+            memo: memo.map(truncate_memo),
+            timestamp: env::block_timestamp().into(),
+        }
+    }
+
+    /// The largest record that can possibly be stored. This is used to measure the storage cost of a history entry.
+    pub(crate) fn longest() -> Self {
+        Self {
+            counterparty: AccountId::new_unchecked("a".repeat(64)),
+            amount: U128(Balance::MAX),
+            direction: TransferDirection::Outgoing,
+            memo: Some("a".repeat(MAX_HISTORY_MEMO_LEN)),
+            timestamp: U64(u64::MAX),
+        }
+    }
+}
+
+/// A bounded ring buffer of the most recent transfers of an account
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct TransferHistory {
+    /// The slot that the next record will be written to
+    pub next_index: u32,
+    /// The stored records. Once the buffer is full, the oldest record is overwritten first.
+    pub records: Vec<TransferRecord>,
+}
+
+impl TransferHistory {
+    /// Adds a record to the buffer, evicting the oldest records if there are more than `capacity`.
+    pub(crate) fn push(&mut self, record: TransferRecord, capacity: u32) {
+        let capacity = capacity as usize;
+        let len = self.records.len();
+        let next_index = self.next_index as usize;
+
+        if len < capacity && next_index == len {
+            // The buffer isn't full yet so we can simply append the record
+            self.records.push(record);
+            self.next_index = (self.records.len() % capacity) as u32;
+        } else if len == capacity && next_index < capacity {
+            // The buffer is full so we overwrite the oldest record
+            self.records[next_index] = record;
+            self.next_index = ((next_index + 1) % capacity) as u32;
+        } else {
+            // The capacity changed since the buffer was last written. Lay the records out from oldest
+            // to newest again and only keep the newest ones that still fit.
+            let mut records = self.chronological();
+            let excess = (records.len() + 1).saturating_sub(capacity);
+            records.drain(..excess);
+            records.push(record);
+            self.records = records;
+            self.next_index = (self.records.len() % capacity) as u32;
+        }
+    }
+
+    /// Drops the oldest records so that at most `capacity` are left. The records are laid out from oldest to newest
+    /// again so that the next push overwrites the oldest one.
+    pub(crate) fn trim(&mut self, capacity: u32) {
+        let capacity = capacity as usize;
+        if self.records.len() <= capacity {
+            return;
+        }
+        let mut records = self.chronological();
+        records.drain(..records.len() - capacity);
+        self.records = records;
+        self.next_index = 0;
+    }
+
+    /// Returns the records ordered from oldest to newest.
+    pub(crate) fn chronological(&self) -> Vec<TransferRecord> {
+        let next_index = self.next_index as usize;
+        self.records[next_index..]
+            .iter()
+            .chain(self.records[..next_index].iter())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Truncates a memo to at most `MAX_HISTORY_MEMO_LEN` bytes without splitting a character.
+fn truncate_memo(memo: &str) -> String {
+    let mut end = std::cmp::min(memo.len(), MAX_HISTORY_MEMO_LEN);
+    while !memo.is_char_boundary(end) {
+        end -= 1;
+    }
+    memo[..end].to_string()
+}
+
+impl Contract {
+    /// Internal method for recording a transfer in the history of both the sender and the receiver.
+    /// Does nothing if the history is disabled. Accounts whose storage deposit doesn't cover a full history are skipped.
+    pub(crate) fn internal_record_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<&str>,
+    ) {
+        if self.transfer_history_size == 0 {
+            return;
+        }
+
+        let outgoing = TransferRecord::new(receiver_id, amount, TransferDirection::Outgoing, memo);
+        self.internal_push_transfer_record(sender_id, outgoing);
+
+        let incoming = TransferRecord::new(sender_id, amount, TransferDirection::Incoming, memo);
+        self.internal_push_transfer_record(receiver_id, incoming);
+    }
+
+    /// Internal method for adding a record to the history of a single account. If the account's storage deposit
+    /// doesn't cover a full history, for example because it registered before the history was enabled, nothing is
+    /// recorded until it tops its deposit up with `storage_deposit`.
+    fn internal_push_transfer_record(&mut self, account_id: &AccountId, record: TransferRecord) {
+        let storage_paid = self.internal_get_account(account_id).map(|account| account.storage_paid).unwrap_or(0);
+        if storage_paid < self.storage_balance_bounds().min.0 {
+            return;
+        }
+
+        let mut history = self.transfer_history.get(account_id).unwrap_or_default();
+        history.push(record, self.transfer_history_size);
+        self.transfer_history.insert(account_id, &history);
+    }
+
+    /// Internal method for dropping the records of an account's history that no longer fit in the history size, for
+    /// example after the owner shrank or disabled the history. The history is removed once it's empty.
+    pub(crate) fn internal_trim_transfer_history(&mut self, account_id: &AccountId) {
+        let mut history = match self.transfer_history.get(account_id) {
+            Some(history) => history,
+            None => return,
+        };
+        if history.records.len() <= self.transfer_history_size as usize {
+            return;
+        }
+
+        history.trim(self.transfer_history_size);
+        if history.records.is_empty() {
+            self.transfer_history.remove(account_id);
+        } else {
+            self.transfer_history.insert(account_id, &history);
+        }
+    }
+
+    /// The number of bytes that the stored history of an account takes up at most. Records are counted as the largest
+    /// possible record.
+    pub(crate) fn internal_transfer_history_bytes(&self, account_id: &AccountId) -> StorageUsage {
+        self.transfer_history
+            .get(account_id)
+            .map(|history| {
+                self.bytes_for_transfer_history + self.bytes_per_transfer_record * history.records.len() as StorageUsage
+            })
+            .unwrap_or(0)
+    }
+
+    /// Internal method for measuring how many bytes a history takes up. This measures the storage of an empty
+    /// history for the longest possible account ID and the size of the largest possible record. It is called in the
+    /// initialization function.
+    pub(crate) fn measure_bytes_for_transfer_history(&mut self) {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
+        self.transfer_history.insert(&tmp_account_id, &TransferHistory::default());
+        self.bytes_for_transfer_history = env::storage_usage() - initial_storage_usage;
+        self.transfer_history.remove(&tmp_account_id);
+
+        self.bytes_per_transfer_record =
+            TransferRecord::longest().try_to_vec().unwrap().len() as StorageUsage;
+    }
+
+    /// The number of bytes that every registered account needs to be able to store its full transfer history.
+    pub(crate) fn bytes_for_full_transfer_history(&self) -> StorageUsage {
+        if self.transfer_history_size == 0 {
+            return 0;
+        }
+        self.bytes_for_transfer_history
+            + self.bytes_per_transfer_record * StorageUsage::from(self.transfer_history_size)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets how many recent transfers are kept for each account. A size of 0 disables the history.
+    /// Only the owner can call this method.
+    ///
+    /// The storage required for a full history is included in `storage_balance_bounds`, so this changes the
+    /// registration cost of new accounts. Accounts that registered for less only get a history once they top their
+    /// deposit up with `storage_deposit`. Shrinking the size evicts the oldest records the next time an account's
+    /// history is written or it calls `storage_withdraw`. Until then, the records that no longer fit are hidden from
+    /// `ft_transfers_of` and the deposit that pays for them stays locked.
+    pub fn set_transfer_history_size(&mut self, size: u32) {
+        self.assert_owner();
+        require!(
+            size <= MAX_TRANSFER_HISTORY_SIZE,
+            format!("The transfer history size can't exceed {}", MAX_TRANSFER_HISTORY_SIZE)
+        );
+        self.transfer_history_size = size;
+    }
+
+    /// Returns how many recent transfers are kept for each account.
+    pub fn ft_transfer_history_size(&self) -> u32 {
+        self.transfer_history_size
+    }
+
+    /// Returns paginated recent transfers of an account, starting with the newest one.
+    pub fn ft_transfers_of(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TransferRecord> {
+        // Get the history of the account. If there is none, return an empty vector
+        let history = match self.transfer_history.get(&account_id) {
+            Some(history) => history,
+            None => return vec![],
+        };
+
+        // Where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        history
+            .chronological()
+            .into_iter()
+            // Newest transfers come first
+            .rev()
+            // Hide the records that no longer fit in the history size but weren't evicted yet
+            .take(self.transfer_history_size as usize)
+            .skip(start as usize)
+            // Take the first "limit" elements. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    fn owner() -> AccountId {
+        AccountId::new_unchecked("owner.near".to_string())
+    }
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice.near".to_string())
+    }
+
+    fn bob() -> AccountId {
+        AccountId::new_unchecked("bob.near".to_string())
+    }
+
+    fn metadata() -> FungibleTokenMetadata {
+        near_sdk::serde_json::from_str(r#"{"spec":"ft-1.0.0","name":"Token","symbol":"TKN","decimals":24}"#).unwrap()
+    }
+
+    fn set_predecessor(predecessor_id: AccountId) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(AccountId::new_unchecked("ft.near".to_string()))
+            .predecessor_account_id(predecessor_id)
+            .build());
+    }
+
+    // A record whose amount tells the records apart
+    fn record(amount: Balance) -> TransferRecord {
+        TransferRecord::new(&bob(), amount, TransferDirection::Outgoing, None)
+    }
+
+    fn amounts(records: &[TransferRecord]) -> Vec<Balance> {
+        records.iter().map(|record| record.amount.0).collect()
+    }
+
+    // Creates a token with a history of `size` records where alice paid for a full history and bob didn't
+    fn setup(size: u32) -> Contract {
+        set_predecessor(owner());
+        let mut contract = Contract::new(owner(), U128(1_000), metadata(), None, None);
+        contract.set_transfer_history_size(size);
+        contract.internal_register_account(&alice(), contract.storage_balance_bounds().min.0);
+        contract.internal_register_account(&bob(), 0);
+        contract.internal_withdraw(&owner(), 100);
+        contract.internal_deposit(&alice(), 100);
+        contract
+    }
+
+    #[test]
+    fn push_overwrites_the_oldest_record_once_full() {
+        set_predecessor(owner());
+        let mut history = TransferHistory::default();
+        for amount in 1..=5 {
+            history.push(record(amount), 3);
+        }
+        assert_eq!(history.records.len(), 3);
+        assert_eq!(history.next_index, 2);
+        assert_eq!(amounts(&history.chronological()), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn push_keeps_the_newest_records_after_the_capacity_changes() {
+        set_predecessor(owner());
+        let mut history = TransferHistory::default();
+        for amount in 1..=4 {
+            history.push(record(amount), 3);
+        }
+
+        // Shrinking evicts the oldest records on the next push
+        history.push(record(5), 2);
+        assert_eq!(amounts(&history.chronological()), vec![4, 5]);
+
+        // Growing keeps every record and appends until the buffer is full again
+        history.push(record(6), 4);
+        history.push(record(7), 4);
+        history.push(record(8), 4);
+        assert_eq!(amounts(&history.chronological()), vec![5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn trim_drops_the_oldest_records() {
+        set_predecessor(owner());
+        let mut history = TransferHistory::default();
+        for amount in 1..=5 {
+            history.push(record(amount), 3);
+        }
+        history.trim(2);
+        assert_eq!(history.next_index, 0);
+        assert_eq!(amounts(&history.chronological()), vec![4, 5]);
+        history.push(record(6), 2);
+        assert_eq!(amounts(&history.chronological()), vec![5, 6]);
+    }
+
+    #[test]
+    fn transfers_are_only_recorded_for_accounts_that_paid_for_a_history() {
+        let mut contract = setup(3);
+        set_predecessor(alice());
+        contract.internal_transfer(&alice(), &bob(), 10, Some("hi".to_string()));
+
+        let transfers = contract.ft_transfers_of(alice(), None, None);
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].counterparty, bob());
+        assert!(transfers[0].direction == TransferDirection::Outgoing);
+        assert_eq!(transfers[0].memo.as_deref(), Some("hi"));
+        assert!(contract.ft_transfers_of(bob(), None, None).is_empty());
+    }
+
+    #[test]
+    fn shrinking_the_history_hides_and_then_evicts_records() {
+        let mut contract = setup(3);
+        set_predecessor(alice());
+        for amount in 1..=3 {
+            contract.internal_transfer(&alice(), &bob(), amount, None);
+        }
+        assert_eq!(amounts(&contract.ft_transfers_of(alice(), None, None)), vec![3, 2, 1]);
+
+        set_predecessor(owner());
+        contract.set_transfer_history_size(1);
+        // The stale records are hidden but still stored
+        assert_eq!(amounts(&contract.ft_transfers_of(alice(), None, None)), vec![3]);
+        assert_eq!(contract.transfer_history.get(&alice()).unwrap().records.len(), 3);
+
+        contract.internal_trim_transfer_history(&alice());
+        assert_eq!(contract.transfer_history.get(&alice()).unwrap().records.len(), 1);
+
+        // Disabling the history removes it entirely once it's trimmed
+        contract.set_transfer_history_size(0);
+        contract.internal_trim_transfer_history(&alice());
+        assert!(contract.transfer_history.get(&alice()).is_none());
+    }
+}
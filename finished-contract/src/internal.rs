@@ -1,12 +1,19 @@
-use near_sdk::{require};
+use near_sdk::{assert_one_yocto, require, Promise};
+
+use crate::storage::StorageManagement;
 
 use crate::*;
 
 impl Contract {
     /// Internal method for force getting the balance of an account. If the account doesn't have a balance, panic with a custom message.
     pub(crate) fn internal_unwrap_balance_of(&self, account_id: &AccountId) -> Balance {
-        match self.accounts.get(account_id) {
-            Some(balance) => balance,
+        self.internal_shares_to_amount(self.internal_unwrap_account(account_id).balance)
+    }
+
+    /// Internal method for force getting the record of an account. If the account isn't registered, panic with a custom message.
+    pub(crate) fn internal_unwrap_account(&self, account_id: &AccountId) -> AccountRecord {
+        match self.internal_get_account(account_id) {
+            Some(account) => account,
             None => {
                 env::panic_str(format!("The account {} is not registered", &account_id).as_str())
             }
@@ -15,31 +22,48 @@ impl Contract {
 
     /// Internal method for depositing some amount of FTs into an account. 
     pub(crate) fn internal_deposit(&mut self, account_id: &AccountId, amount: Balance) {
-        // Get the current balance of the account. If they're not registered, panic.
-        let balance = self.internal_unwrap_balance_of(account_id);
-        
-        // Add the amount to the balance and insert the new balance into the accounts map
-        if let Some(new_balance) = balance.checked_add(amount) {
-            self.accounts.insert(account_id, &new_balance);
+        // Add the amount to the balance and insert the new balance into the accounts map. If the contract is
+        // rebasing, the amount is converted to shares first.
+        let shares = self.internal_amount_to_shares(amount);
+        self.internal_deposit_shares(account_id, shares);
+    }
+
+    /// Internal method for depositing some shares into an account. Without rebasing, shares are token amounts.
+    pub(crate) fn internal_deposit_shares(&mut self, account_id: &AccountId, shares: Balance) {
+        // Get the current record of the account. If they're not registered, panic.
+        let mut account = self.internal_unwrap_account(account_id);
+
+        if let Some(new_balance) = account.balance.checked_add(shares) {
+            account.balance = new_balance;
+            self.internal_set_account(account_id, &account);
         } else {
             env::panic_str("Balance overflow");
         }
     }
 
-    /// Internal method for withdrawing some amount of FTs from an account. 
+    /// Internal method for withdrawing some amount of FTs from an account.
     pub(crate) fn internal_withdraw(&mut self, account_id: &AccountId, amount: Balance) {
-        // Get the current balance of the account. If they're not registered, panic.
-        let balance = self.internal_unwrap_balance_of(account_id);
-        
-        // Decrease the amount from the balance and insert the new balance into the accounts map
-        if let Some(new_balance) = balance.checked_sub(amount) {
-            self.accounts.insert(account_id, &new_balance);
+        // If the contract is rebasing, the amount is converted to shares with the same rounding as in `internal_deposit`.
+        let shares = self.internal_amount_to_shares(amount);
+        self.internal_withdraw_shares(account_id, shares);
+    }
+
+    /// Internal method for withdrawing some shares from an account. Without rebasing, shares are token amounts.
+    pub(crate) fn internal_withdraw_shares(&mut self, account_id: &AccountId, shares: Balance) {
+        // Get the current record of the account. If they're not registered, panic.
+        let mut account = self.internal_unwrap_account(account_id);
+
+        // Decrease the shares from the balance and insert the new balance into the accounts map.
+        if let Some(new_balance) = account.balance.checked_sub(shares) {
+            account.balance = new_balance;
+            self.internal_set_account(account_id, &account);
         } else {
             env::panic_str("The account doesn't have enough balance");
         }
     }
 
-    /// Internal method for performing a transfer of FTs from one account to another.
+    /// Internal method for performing a transfer of FTs from one account to another. The amount counts towards the
+    /// sender's daily transfer limit.
     pub(crate) fn internal_transfer(
         &mut self,
         sender_id: &AccountId,
@@ -51,10 +75,19 @@ impl Contract {
         require!(sender_id != receiver_id, "Sender and receiver should be different");
         // Ensure the sender can't transfer 0 tokens
         require!(amount > 0, "The amount should be a positive number");
+        // Ensure both accounts are on the allowlist if the contract is permissioned
+        self.assert_allowlisted(sender_id);
+        self.assert_allowlisted(receiver_id);
         
+        // Spend the amount from the sender's daily allowance. If it exceeds the limit, panic.
+        self.internal_spend_daily_allowance(sender_id, amount);
+
         // Withdraw from the sender and deposit into the receiver
         self.internal_withdraw(sender_id, amount);
         self.internal_deposit(receiver_id, amount);
+
+        // Record the transfer in the history of both accounts
+        self.internal_record_transfer(sender_id, receiver_id, amount, memo.as_deref());
         
         // Emit a Transfer event
         FtTransfer {
@@ -66,20 +99,89 @@ impl Contract {
         .emit();
     }
 
-    /// Internal method for registering an account with the contract.
-    pub(crate) fn internal_register_account(&mut self, account_id: &AccountId) {
-        if self.accounts.insert(account_id, &0).is_some() {
+    /// Internal method for ensuring that the predecessor is the owner of the contract.
+    pub(crate) fn assert_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.owner_id,
+            "Only the owner can call this method"
+        );
+    }
+
+    /// Internal method for handling the deposit attached to `ft_transfer` and `ft_transfer_call`.
+    ///
+    /// If the receiver is registered, exactly 1 yoctoNEAR must be attached. Otherwise the sender must attach
+    /// at least the minimum storage balance so that the receiver is registered on their behalf. Any excess is refunded to the sender.
+    pub(crate) fn internal_handle_transfer_deposit(&mut self, sender_id: &AccountId, receiver_id: &AccountId) {
+        // If the receiver is already registered, assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
+        if self.internal_get_account(receiver_id).is_some() {
+            assert_one_yocto();
+            return;
+        }
+
+        // Get the minimum required storage and ensure the deposit is at least that amount
+        let amount = env::attached_deposit();
+        let min_balance = self.storage_balance_bounds().min.0;
+        if amount < min_balance {
+            env::panic_str(
+                format!(
+                    "The account {} is not registered. Attach at least {} yoctoNEAR to register it",
+                    receiver_id, min_balance
+                )
+                .as_str(),
+            );
+        }
+
+        // If the contract is permissioned, only accounts on the allowlist can be registered
+        self.assert_allowlisted(receiver_id);
+
+        // Register the receiver and refund any excess $NEAR to the sender
+        self.internal_register_account(receiver_id, min_balance);
+        let refund = amount - min_balance;
+        if refund > 0 {
+            Promise::new(sender_id.clone()).transfer(refund);
+        }
+    }
+
+    /// Internal method for charging the predecessor for the storage they changed since `initial_storage_usage`.
+    /// If the storage grew, the attached deposit must cover it. Any excess deposit, or storage that was freed up, is refunded.
+    pub(crate) fn internal_settle_storage_change(&self, initial_storage_usage: StorageUsage) {
+        let final_storage_usage = env::storage_usage();
+        let attached_deposit = env::attached_deposit();
+
+        let refund = if final_storage_usage > initial_storage_usage {
+            // Ensure the attached deposit covers the extra storage
+            let required_deposit =
+                Balance::from(final_storage_usage - initial_storage_usage) * env::storage_byte_cost();
+            require!(
+                attached_deposit >= required_deposit,
+                format!("Must attach {} yoctoNEAR to cover storage", required_deposit)
+            );
+            attached_deposit - required_deposit
+        } else {
+            // Refund the storage that was freed up along with the deposit
+            attached_deposit + Balance::from(initial_storage_usage - final_storage_usage) * env::storage_byte_cost()
+        };
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    /// Internal method for registering an account with the contract. `storage_paid` is the $NEAR deposited for its storage.
+    pub(crate) fn internal_register_account(&mut self, account_id: &AccountId, storage_paid: Balance) {
+        if self.internal_get_account(account_id).is_some() {
             env::panic_str("The account is already registered");
         }
+        self.internal_set_account(account_id, &AccountRecord { balance: 0, storage_paid });
     }
 
     /// Internal method for measuring how many bytes it takes to insert the longest possible account ID into our map
     /// This will insert the account, measure the storage, and remove the account. It is called in the initialization function.
+    /// Since the map is keyed by the hash of the account ID, every account actually takes up the same number of bytes.
     pub(crate) fn measure_bytes_for_longest_account_id(&mut self) {
         let initial_storage_usage = env::storage_usage();
-        let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
-        self.accounts.insert(&tmp_account_id, &0u128);
+        let tmp_account_key = account_key(&AccountId::new_unchecked("a".repeat(64)));
+        self.accounts.insert(&tmp_account_key, &AccountRecord { balance: 0, storage_paid: 0 });
         self.bytes_for_longest_account_id = env::storage_usage() - initial_storage_usage;
-        self.accounts.remove(&tmp_account_id);
+        self.accounts.remove(&tmp_account_key);
     }
 }
\ No newline at end of file
@@ -1,16 +1,35 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap};
+use near_sdk::collections::{LazyOption, LookupMap, UnorderedSet, Vector};
 use near_sdk::json_types::U128;
-use near_sdk::{env, near_bindgen, AccountId, Balance, PanicOnDefault, StorageUsage};
+use near_sdk::{env, near_bindgen, AccountId, Balance, CryptoHash, PanicOnDefault, StorageUsage};
 
 pub mod ft_core;
 pub mod events;
 pub mod metadata;
 pub mod storage;
 pub mod internal;
+pub mod history;
+pub mod limits;
+pub mod meta_transfer;
+pub mod allowlist;
+pub mod transfer_call_many;
+pub mod gas;
+pub mod memo;
+pub mod accounts;
+pub mod rebase;
+pub mod staking;
 
 use crate::metadata::*;
 use crate::events::*;
+use crate::history::*;
+use crate::limits::*;
+use crate::meta_transfer::*;
+use crate::allowlist::*;
+use crate::gas::*;
+use crate::memo::*;
+use crate::accounts::*;
+use crate::rebase::*;
+use crate::staking::*;
 
 /// The image URL for the default icon
 const DATA_IMAGE_SVG_GT_ICON: &str = "data:image/jpeg;base64,/9j/4AAQSkZJRgABAQAASABIAAD/2wCEABwcHBwcHDAcHDBEMDAwRFxEREREXHRcXFxcXHSMdHR0dHR0jIyMjIyMjIyoqKioqKjExMTExNzc3Nzc3Nzc3NwBIiQkODQ4YDQ0YOacgJzm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5ubm5v/CABEIAUsB8gMBIgACEQEDEQH/xAAaAAEBAQEBAQEAAAAAAAAAAAAAAQIDBAUG/9oACAEBAAAAAPAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAQoAAAAIUAQAoTNzHTcgAAEoQKBAAFTnnrtnXRyACAABGhAAAOeeuunTWr53KAAAIsE2AEKgGNXXTpenfj58cgAQBKSmgAACVrGuu726Z4cuMCABKEpNgAAC76+Z13rvvn5ZzyCEKlJQGgALQCejyZ16+WfV18eJAEBAEo0BbCgDN6eaX6HDl6u/lxIEIAiBLGxY0AATo4S/Q1z308vXzYASAJEqDoBQACb7eSX2bnS8XnyBIBIQEdgAAAvo8eL6O/Ptq8vNAZBEghKjtQUiglDv5Ma7dbvXDGAiERECAdFVQoAHThi9fRno8hEEiEEAF0soUJRQ1iXt3uc+YhEiIAAHQAVLBYG2c9PRq8PMEkJZQAA6ipZSUiovPPXDfTt28XESTLUIpALFdxRFixZUXhntTt36eDjoSYtJCoAZ09AKIsssqMc2u3XPTtv53K0kiwypADOnoBUBZZM5zldb9W51fN7YkRmkQM0APQFsBUxztZkq69Tvw4bZlmc1JcrIAB6c6ttYK048bOhiyw68+vHruY1JJckJAAPTNVrV5S1vn5prPSyQ9XLi5+rE10wY7c4lyiZpYB6JutavGatvlxbL057w36O3z+d9GdSxnPVzsSX1eXkqwDtOtbt4TdPEvXM7c2ue+vq+fjr6OFWa5zpcZWTfu8nmliwHWdpnd6+adbnHmrqx2zjrnPp7+Hl29Xmpbnn1sxLHT1+LnElgOk7Sdo4usnLjuOnPtvdk138PLfRKtcemmJWevbySWSQG3bV6Seedda8eNJrPXp2XWe/i8+tpba471c50nXp5AmZBe021UxN2ebOtZ9/1vL8zvb6vF39k4fJ1Zq1x1uaxTp3xnz25mcxbsKFuOeXSfufn/R/OeH6uPDrr97p8L5P2uXyO31+Xx/rc/V4PofHGvZ0vh5CTnLdABcZS9f23571fK+v39n5/zX6/q/IfpOvt/NfVx9X8n+m8P0vm/T/P/Njp7ZL4JbLMYnZKoHCF6/tfl9Pj/a4/Q+N4+X2/V+O/W9u3wPL9P635j9D8n7XxPu/A+bma9XWY8+ahOE7pVA4SXW/23wfu/A+lx+h8bwPs+r8X+w4ef5/2H0/zH6H5P2vifd+B83PPp6tXfm52SacM5pKRaLc/uPF7vge7fs+D5+f2vV+L/V31fA+x5vr/AJf9F8j7XxPu/n/mZ5du3ezhCZ1rE5yVQOU336Xr9Dz+Pn9jl8btn19fk+v6nP4f0PVv5P0/F7vB9D5/n15fTvvvPCiWr5SoNLz6+mznz93o+XmYskuvVjyVszjGtbrjN99Yb16POF+aNNa755T0fRz5+Ens9Xz8cVyq+nfga0Yzhvr0xKbl1r2eIV8yu+d9efTjyn0vX5OOT1evx+flblV9N8V1TGM67X0eeddYsXfs8cK//8QAFwEBAQEBAAAAAAAAAAAAAAAAAAECA//aAAgBAhAAAADAAAAAFgAAAAFgoIpAFAIoAZS2gAIFSiSS6oCBYACXJopAFgADJaACoAEXKaoChchAFyaBQCAAlytKACJSUCTRQKSsiVKVmNFAsUygNJSSyqAUyixoi51AFlsUyBSE1CWUmhSQFJE0JQlUICmpmXckoiTRSApoc+koMzJS6gSmhz6SgzJFGgEmpUsoqGSVdwlwsztZFKhDOl//xAAXAQEBAQEAAAAAAAAAAAAAAAAAAQID/9oACAEDEAAAANAAAAAAAAAAAAAWAAIogspqpkIFEoALq2YgAAAFrVmIAACgLOkucywAFJRGrNkwQATQAK1ZiAANAANXOSLADQAWLrOSVKgNAFVZpjIlQDQDWkDWpOUEWCga1dSRdLHAJYKlG7akatS8szWZULKLpdSTV0ubnkuRCg1bw3uZxves2JNMkig6RxHbjvAOs1ZDOdA6JxL146yDvlRGIK6Jk3rGLuIsmguMUs6RbdRx101Oa5lXOrj/xAA8EAACAgEBBQQIBAUEAgMAAAAAAQIRAyEEBRASMRMgQVEiMDIzNEBxcmGBscEUFSNCUFJTodFDkWKA4f/aAAgBAQABPwD/AOi9ll/5puhTVjlb04piaZy2OLj/AJZuhsSsSOU5Ds2KDRG71G09GU185ZfzEnbErZVCEhLgkchOLTseqGvl7+da1Ix8eCFxiUqJwtMa0K0+Sv8AwUo+Il6PBEUMRFCY2NDH3n/jW9KFFLFb68E0RaE7ItIi0fQl0JSfQsfX/B18gmTdRGxkZakFaJ6MjKV6EMj8TqZFUiyvU0UUUUUV8rRXrXd0iulmV6UUzVdSJjXoGSLshFqRya2hIy+1oRher0Gqdd6ivVUUV69fIf3JkVzToy9SihLUxP0aJRTOSiK4TrtBxSjZL5K/kK+QfQwv0kZorrxi0nqQmktSTtWiMrI0NnJbcmQdwaJdfU33771l8b+Zi6kjJ0ofBK2R6UyKVUinFkX4jdiVqifLjVIbv1tl92/8B4k9V+Q0WhNITQpUcykQ6DO1km6Jycnb9ZfyNnMcxZZZZZZfr5PQZQkR6kUvElGtUReg3p3bL4ssvjZZfzdlllllllllllid6FajVCIVZSHqjoTdQL71l8LLL/xcOo3qSdoTEyMtBM6md1USyy2Wyyyyyyyyyyyyyyyyyyyyyyyy/WL1KH3JtroQnLmJO+CExNkdRGd3P1F/JWWWWX8o+LZJ2xaOxFWOLEiERLhkdyZfqF8mvlUPhaRKV9OMXRF2VY0RI8JP+q0iUWmLuP1t/PcyQ5N9C2PU6dyLoxyUtBxo6EOhknyRZC+e2PUcSuL+dr1K4yn4IcmJ3wa4NC7kZNO0Rz37SJTizHOLVWZpczpCpPXjLQjqteDSorxGLu0V8g+CRRRRRy92iikTklouFCdPjVo6dRrxQnfUorhZGHNG2xS5ZF3qZLshNNU+vCStEPIY+hB+BKPiL5Z91cPAfcXDJKlXmPrwRLQi7QxPwJITGvFEX5kMcX1MuLkf4DR2kkqR1ZFUiSOTTQUmtJcFoyR4EfaOo1XqWX6xsXXuLg+gxcVwySuZLqRHoNWjH5DHoLVDQmUupFk1z42vFH4DIK5cJasXFdSXQXQS1ESVruvhV6GPBGa66mSDhJxfgMXq5dRMXGOvDwJuiPFEnUWzxsYirXCOkhjIsaHoJ6EeiZB6EvaYzBHmdLxMuKWJq/EfXuLrwWloQuEuvdRFamLQ2jXKyvWSF1EOSRzmOT1Of0jmVE3ZERaRzGV+jXci7GjxFqhniJ2hoWglWNMh0JdWSMMuV8y8DNneWlVUPrwXBcGjo6E+Eu6iBBmR802+5XqWLqIcbFAhEcNRw0JaERDVigZetcEMToeqGR6EEndnJHyORHJE7JEuvKvAxdKJqpMkYx8VwXGXUXTi1xaERRfLFsY+FFFeoYuouCQtCxsn1IiL4SdtvuUJ+BIj0MXi+Cd9Dll1oUXV0KPpakI0zJCXM3RJNdSHTvLjPqR6cZdxCMsax2u7RQ0V3aGkcq4WWcxZY42KNcZOoj4IobUVbNj3Zl2tLLlfJjfTzYtzbElTUm/OzPuVJOWyzafk+hi5oSliyKpx6o04avQi1CNMc7baNh3dHa9nWeeSabb6M/kuL/dyf+0fyPA//JP/AINo3Phw4J5Y5JNxV+BB3BN9xC4z6i6CPHguKE9Uc6qnqiWKM/Z0Hs0+q1GmnTFwooaGuK4Wyy2Wy2WWWzmOY5jmJytcGhMTHDtMkMf+qSRFKKUY6JaI23bZbLkxQjFNZHT4b3xqG04syXtaMx4821ZXi2fSusvIjuTFX9XLNv8ADT/sybnnj9LZcrvykdtOV4cq5ckeqFj9F/Q2LeMdj2dYcmOTab6GzbRHacSzQTSfmZcixY5ZH0irNo3vhzYJ44wlclSNlwZ9rfZYdEurfgQ3Hir+pkk3+Gn/AGZtyzgnLZsjb8pf9iclJ48iqUeqNnwZ9sm4YdEusmR3Hir+pkm3+Ghl3K4py2bI78pE5zhGUJrlnHRmPc0skIz7Z+kk+n/6Zdz5oOKxZHJydPSkkLceLl9LJLm810P5Ztaz/wAOn6PXn/A/keHl95Lm8/Ay4cuy53gy6+Kfmu5FWyEElZQp8pnX9Rvz7lFDVjjRXGmUUUUUUUUUUUPjQjD8Vh+9frw31JQy4JPom3/yj+dbF/8AL/0bz2/DtcYLDdxd6o3RiWPY1KtZttm8M+TZ9lllxupJr9SD5oKT8Ub4xqGbFnjo5eiyMzJNckvozdPwMPz/AFNs+FyfaypQwRl5o3TiWPYovxnqzeOfJs+zPJjdO0iLuKb8Ub7x9nnhmiq5k0/yN1Y1j2KDXWWrNv2rLs+fBDG9Jy14b6hybUpL++Js/uMf2r9Dem1Zdlwxli0blRF3FPzRvLasuzLF2Trmlr9Bao3yl22GXjqu5jrm1PAcvISb6kscMi/EkuV15dyuDQ4jdF9+iiiiih9e4jF8Vh+9frw2zDjz7Zgx5VzRalofyzYf9pf8m99k2fZ4Y3hjytt2bt+BxfT9zfHwMvqv1MXu4/RG+emH7jkXgTg1F+VG6fgYfn+ptnwuT7WSleCK8kbu+CxfabRs+PacfZZbq70/ASpJLwN+q44kvNmy702nHCOzYsSm4qvGz+F2jbMmPNtaWPs3ajHV/nw377/H9v7mz+4x/av0N++4h937EPYj9Eb76YPuf7C6I3z73B+Y+CIunoRbfUTSJSQnJv0SV8zvr330GUX3LL4WWWWPhXGjD8Vh+9frw3tKUdo2dxbTt9PquG/fd4/qzdvwOL6fub4+Bl9V+pi93H6I310w/ccxKb5H9Gbp+Bh+f6m2fC5PtYvdfkbu+Cxfab3lKGxtwbT5l0MG7u1wwyvPkTkk+pvTZP4VY2sk5237Ts3Tghi2SM0vSnq2bw2qeB4sWPR5JJN/hfDfvv8AH9v7mz+4x/av0N++4h937EPYj9Eb7/8AB9z/AGI9Eb695h/PgyPWxMjJUWct9SLUGZmnktdx8EPoS4c7O0O0O0Oc7Q7Q5ztDnO0FO9BlmjEkUzmePJDL/pkmJqSUo6p6m17G9qyYp81dm7fDfvu8X1ZunIp7FBL+20byw5M+ySx4lcrWn5kFywin4JG98nNnw4V1XpMqLJxXI2vJm6fgYfn+ptfwuT7WRV46XkbqyrJsUF4x0ZvPDkz7I4YlzStOjZ4PHghjl1jFJm/cilPHhXWKbf5m7tdixV/pN47Nmz5sE8atRlr+GvDf3vsf2/ubP7jH9q/Q377iH3fsQ9iP0Rvvpg+5/sR6I317zD+Yn4GT2SEvARjq9RMbYk2TVTfcZQuDhZ2Z2J2J2LOxZ2LOxOxOxOxOxOxOyrUfCKb0RHH5ihFEoRlFpmybxy7GuyzLnxro11RLfWzV/TjKUvKjFvXPDm7fDKVu1XgvI27bltvJGONx5Xeps205thm5Y1zQl1iR33srXpxlF+VWZd946a2eDk/N6IUsk8jzZXc5HaeZPLcWl4o2Pekdl2eOGWOUmr1Rm3xDLiljWKS5k0Yk0kmbPk2jZMjyYFzRfWJHfez9MsZRa8Kszb7x01s8HKXm+hJzySlkyu5S6mwby/g49hmi3C9GvAz76g0o7NGTdq2/I/nkP9mRvDant2SM4QceVVqY98wx44weKT5Ukbw3gttxxhHHKPK71I78xqKXZS0Xmbft62zs+WDjyO9Rb8xpV2Ujbds/jZ43GDjy31F7SMlctITp2J2hOiLtWWhzolLmlfdoXc7ZnbHbHbHbHbHbHbMWVs7RnaM7RjjbshictERxKC0FHzHSHPwLIP0Uy7hRNVNoSJvShd6PUxaTTNsjy5W/Piu4yQiyyI148JQ8hScRTZGTo5n3F5GSKeJNf2v1NFHKzkZ2chY/MjBJEqG0cyIRc2orxFFQVIlJRVslksci+GJ3FEfZaMy9M6IkxcH3I+0I2upJNeHfZLjFNlHLpY+HZpiwxYoKL0K4oj7SIq8cl3/HguokqINuzG23qT06FvmS4SHw2Ra/kTMjdj7mHoR6Gb2iQxD7q6iM/u/z77Hwjq+K1VD4LoR6njwfGHtGPoyXV97/xAAgEQADAAMAAgIDAAAAAAAAAAAAAREQIEAwUAISITFg/9oACAECAQE/AP66l9ChdkxBd9EX0C716Bda1XVMv0MJ3XD8N534WLhei7Gj6jR8cMhCCRCaXmW7w8LRYhCEITK3W7w9kXH52uPsL5UTLilKUpSlGIhMra6PLFqxfrwXRaf/xAAjEQACAgICAgIDAQAAAAAAAAAAARARAiAxQBITITADIkFg/9oACAEDAQE/AP8AWpFFDXdUsfcTix99IY12lKnJ91Q3163UZdtFQoyXZoqaKirQ8WiutQiipuE/4OGhqumhC5GPTEoU5K1pX3KEMYpYtHGSlD+1S5sQxaMRkrXRUN/FlmOVMeaFkmZFmGVcnsPNJWe1GWaPYexSitKmiipqcuNFOX83xX6woejiyyyyy5y40U5b/j4Mvhy9H9NlnieJ4iwoo9ZlyLGx4pnrPWeB4CVGRZZcJ7Vohwhi0z5MJY4cVL4MdP/Z";
@@ -18,13 +37,34 @@ const DATA_IMAGE_SVG_GT_ICON: &str = "data:image/jpeg;base64,/9j/4AAQSkZJRgABAQA
 /// The specific version of the standard we're using
 pub const FT_METADATA_SPEC: &str = "ft-1.0.0";
 
+/// The maximum number of accounts that can be queried at once in the batch view methods
+pub const MAX_BATCH_VIEW_ACCOUNTS: usize = 500;
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
-    /// Keep track of each account's balances
-    pub accounts: LookupMap<AccountId, Balance>,
+    /// The account that's allowed to configure the contract
+    pub owner_id: AccountId,
+
+    /// Keep track of each account's balances, keyed by the hash of the account ID. Balances are shares if the contract is rebasing.
+    pub accounts: LookupMap<CryptoHash, AccountRecord>,
+
+    /// The balances of contracts deployed before account keys were hashed. Entries are moved to `accounts` as they're written.
+    pub legacy_accounts: LookupMap<AccountId, Balance>,
+
+    /// Whether every entry of `legacy_accounts` was moved to `accounts`, so that the old map no longer needs to be read
+    pub legacy_accounts_migrated: bool,
 
-    /// Total supply of all tokens.
+    /// How many entries of `legacy_accounts` haven't been moved to `accounts` yet
+    pub legacy_accounts_remaining: u64,
+
+    /// The sum of the balances in `legacy_accounts` that haven't been moved yet. This starts at the old total supply.
+    pub legacy_balance_remaining: Balance,
+
+    /// The $NEAR that accounts in `legacy_accounts` paid for their storage when they registered with the old layout
+    pub legacy_storage_paid: Balance,
+
+    /// Total supply of all tokens. This is the total of all shares if the contract is rebasing.
     pub total_supply: Balance,
 
     /// The bytes for the largest possible account ID that can be registered on the contract 
@@ -32,13 +72,63 @@ pub struct Contract {
 
     /// Metadata for the contract itself
     pub metadata: LazyOption<FungibleTokenMetadata>,
+
+    /// The JSON document the metadata's `reference` points to, verified against its `reference_hash`
+    pub metadata_reference: LazyOption<Vec<u8>>,
+
+    /// The icon of the token, split into chunks and stored separately from the rest of the metadata
+    pub icon_chunks: Vector<String>,
+
+    /// The maximum size of the icon in bytes
+    pub max_icon_len: u32,
+
+    /// Keep track of the most recent transfers of each account
+    pub transfer_history: LookupMap<AccountId, TransferHistory>,
+
+    /// How many recent transfers are kept for each account. 0 means the history is disabled.
+    pub transfer_history_size: u32,
+
+    /// The bytes for an empty transfer history of the longest possible account ID
+    pub bytes_for_transfer_history: StorageUsage,
+
+    /// The bytes for the largest possible record in a transfer history
+    pub bytes_per_transfer_record: StorageUsage,
+
+    /// Keep track of the self-imposed daily transfer limits of accounts that opted into one
+    pub daily_limits: LookupMap<AccountId, DailyLimit>,
+
+    /// Keep track of the keys each account registered for signing transfer intents, and their nonces
+    pub signer_accounts: LookupMap<AccountId, SignerAccount>,
+
+    /// The allowlist of a permissioned deployment. `None` if any account can register and transfer tokens.
+    pub permissioning: Option<Permissioning>,
+
+    /// The gas budgets used for `ft_transfer_call` and its resolve callback
+    pub gas_settings: GasSettings,
+
+    /// The maximum lengths of transfer memos and `ft_transfer_call` msgs
+    pub message_limits: MessageLimits,
+
+    /// The share index of a rebasing deployment. `None` if balances are plain token amounts.
+    pub rebasing: Option<Rebasing>,
+
+    /// The pool that holders can stake their tokens in to earn rewards
+    pub staking: StakingPool,
 }
 
 /// Helper structure for keys of the persistent collections.
 #[derive(BorshSerialize)]
 pub enum StorageKey {
     Accounts,
-    Metadata
+    Metadata,
+    TransferHistory,
+    DailyLimits,
+    SignerAccounts,
+    Allowlist,
+    MetadataReference,
+    IconChunks,
+    AccountsHashed,
+    Stakes,
 }
 
 #[near_bindgen]
@@ -60,47 +150,124 @@ impl Contract {
                 reference_hash: None,
                 decimals: 24,
             },
+            None,
+            None,
         )
     }
 
     /// Initializes the contract with the given total supply owned by the given `owner_id` with
     /// the given fungible token metadata.
+    ///
+    /// If `permissioned` is true, only accounts on the allowlist can register and transfer tokens. The owner is
+    /// added to the allowlist. This can't be changed after initialization.
+    ///
+    /// If `rebasing` is true, balances are tracked as shares whose token amount follows an index that the owner can
+    /// raise or lower (see the `rebase` module). This can't be changed after initialization either.
     #[init]
     pub fn new(
         owner_id: AccountId,
         total_supply: U128,
         metadata: FungibleTokenMetadata,
+        permissioned: Option<bool>,
+        rebasing: Option<bool>,
+    ) -> Self {
+        // Create the contract state with all the fields initialized
+        let mut this = Self::internal_init_state(
+            owner_id.clone(),
+            total_supply.0,
+            metadata,
+            permissioned.unwrap_or(false),
+            rebasing.unwrap_or(false),
+        );
+
+        // Register the owner's account and set their balance to the total supply. The contract covers the owner's storage.
+        this.internal_register_account(&owner_id, 0);
+        this.internal_deposit(&owner_id, total_supply.into());
+        
+        // Emit an event showing that the FTs were minted
+        FtMint {
+            owner_id: &owner_id,
+            amount: &total_supply,
+            memo: Some("Initial token supply is minted"),
+        }
+        .emit();
+
+        // Return the Contract object
+        this
+    }
+}
+
+impl Contract {
+    /// Creates the contract state with all the fields initialized, without registering any account.
+    /// This is shared by the initialization and migration functions.
+    pub(crate) fn internal_init_state(
+        owner_id: AccountId,
+        total_supply: Balance,
+        metadata: FungibleTokenMetadata,
+        permissioned: bool,
+        rebasing: bool,
     ) -> Self {
         // Create a variable of type Self with all the fields initialized. 
         let mut this = Self {
+            // Set the owner of the contract
+            owner_id: owner_id.clone(),
             // Set the total supply
-            total_supply: total_supply.0,
+            total_supply,
             // Set the bytes for the longest account ID to 0 temporarily until it's calculated later
             bytes_for_longest_account_id: 0,
             // Storage keys are simply the prefixes used for the collections. This helps avoid data collision
-            accounts: LookupMap::new(StorageKey::Accounts.try_to_vec().unwrap()),
+            accounts: LookupMap::new(StorageKey::AccountsHashed.try_to_vec().unwrap()),
+            // The old map is kept under its original prefix so that migrated contracts can still read it
+            legacy_accounts: LookupMap::new(StorageKey::Accounts.try_to_vec().unwrap()),
+            // A new contract has nothing to migrate
+            legacy_accounts_migrated: true,
+            legacy_accounts_remaining: 0,
+            legacy_balance_remaining: 0,
+            legacy_storage_paid: 0,
+            // The icon is stored separately so that the metadata can be read without it
             metadata: LazyOption::new(
                 StorageKey::Metadata.try_to_vec().unwrap(),
-                Some(&metadata),
+                Some(&FungibleTokenMetadata { icon: None, ..metadata.clone() }),
             ),
+            metadata_reference: LazyOption::new(StorageKey::MetadataReference.try_to_vec().unwrap(), None),
+            icon_chunks: Vector::new(StorageKey::IconChunks.try_to_vec().unwrap()),
+            // Start with the default maximum icon size. The owner can change it later.
+            max_icon_len: DEFAULT_MAX_ICON_LEN,
+            transfer_history: LookupMap::new(StorageKey::TransferHistory.try_to_vec().unwrap()),
+            // The transfer history is disabled until the owner sets a size
+            transfer_history_size: 0,
+            // Set the bytes for the transfer history to 0 temporarily until they're calculated later
+            bytes_for_transfer_history: 0,
+            bytes_per_transfer_record: 0,
+            daily_limits: LookupMap::new(StorageKey::DailyLimits.try_to_vec().unwrap()),
+            signer_accounts: LookupMap::new(StorageKey::SignerAccounts.try_to_vec().unwrap()),
+            // Only permissioned deployments keep an allowlist
+            permissioning: None,
+            // Start with the default gas budgets. The owner can tune them later.
+            gas_settings: GasSettings::default(),
+            // Start with the default memo and msg limits. The owner can change them later.
+            message_limits: MessageLimits::default(),
+            // Rebasing deployments start with one share per token unit
+            rebasing: if rebasing { Some(Rebasing::default()) } else { None },
+            // Staking earns no rewards until the owner sets an APR
+            staking: StakingPool::new(),
         };
 
+        // If the contract is permissioned, create the allowlist and add the owner to it
+        if permissioned {
+            let mut allowlist = UnorderedSet::new(StorageKey::Allowlist.try_to_vec().unwrap());
+            allowlist.insert(&owner_id);
+            this.permissioning = Some(Permissioning { allowlist, compliance_id: None });
+        }
+
+        // Store the icon in its own chunks
+        this.internal_set_icon(metadata.icon);
+
         // Measure the bytes for the longest account ID and store it in the contract.
         this.measure_bytes_for_longest_account_id();
+        // Measure the bytes for the transfer history and store them in the contract.
+        this.measure_bytes_for_transfer_history();
 
-        // Register the owner's account and set their balance to the total supply.
-        this.internal_register_account(&owner_id);
-        this.internal_deposit(&owner_id, total_supply.into());
-        
-        // Emit an event showing that the FTs were minted
-        FtMint {
-            owner_id: &owner_id,
-            amount: &total_supply,
-            memo: Some("Initial token supply is minted"),
-        }
-        .emit();
-
-        // Return the Contract object
         this
     }
-}
\ No newline at end of file
+}
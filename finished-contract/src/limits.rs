@@ -0,0 +1,278 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::*;
+
+/// The length of a transfer limit window in nanoseconds (24 hours)
+pub const DAILY_LIMIT_WINDOW: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// The length of a spending bucket in nanoseconds (1 hour). Spends are summed per hour so that the window can slide
+/// without storing every transfer.
+pub const DAILY_LIMIT_BUCKET: u64 = 60 * 60 * 1_000_000_000;
+
+/// The number of hourly buckets that are kept: a full window plus the current hour. A spend keeps counting towards the
+/// limit until its whole hour has left the window, so between 24 and 25 hours after it happened.
+pub const DAILY_LIMIT_BUCKETS: u64 = DAILY_LIMIT_WINDOW / DAILY_LIMIT_BUCKET + 1;
+
+/// How long it takes for a raised (or removed) limit to take effect in nanoseconds (24 hours)
+pub const DAILY_LIMIT_TIMELOCK: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// A limit that was raised but hasn't taken effect yet
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct PendingDailyLimit {
+    /// The new limit. `None` means the limit is being removed.
+    pub limit: Option<Balance>,
+    /// The block timestamp at which the new limit takes effect
+    pub effective_at: u64,
+}
+
+/// The amount of tokens an account sent within a single hour
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct SpendBucket {
+    /// The hour the tokens were sent in, counted in hours since the unix epoch
+    pub hour: u64,
+    /// How many tokens were sent in that hour
+    pub amount: Balance,
+}
+
+/// The self-imposed limit on how many tokens an account can send within any 24 hour window
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct DailyLimit {
+    /// The maximum amount of tokens that can be sent in a window. `None` means there is no limit.
+    pub limit: Option<Balance>,
+    /// A raised limit that will take effect once its timelock has passed
+    pub pending: Option<PendingDailyLimit>,
+    /// A ring buffer of `DAILY_LIMIT_BUCKETS` hourly buckets. The bucket of an hour is at `hour % DAILY_LIMIT_BUCKETS`.
+    /// All buckets are allocated up front so that the storage of the limit never grows.
+    pub buckets: Vec<SpendBucket>,
+}
+
+impl DailyLimit {
+    /// Creates a limit with nothing spent yet.
+    pub(crate) fn new(limit: Option<Balance>) -> Self {
+        Self {
+            limit,
+            pending: None,
+            buckets: vec![SpendBucket::default(); DAILY_LIMIT_BUCKETS as usize],
+        }
+    }
+
+    /// Applies a pending limit whose timelock has passed.
+    pub(crate) fn refresh(&mut self, now: u64) {
+        if let Some(pending) = self.pending.clone() {
+            if now >= pending.effective_at {
+                self.limit = pending.limit;
+                self.pending = None;
+            }
+        }
+    }
+
+    /// The buckets that still count towards the limit at `now`.
+    fn live_buckets(&self, now: u64) -> impl Iterator<Item = &SpendBucket> {
+        let current_hour = now / DAILY_LIMIT_BUCKET;
+        self.buckets
+            .iter()
+            .filter(move |bucket| bucket.amount > 0 && current_hour - bucket.hour < DAILY_LIMIT_BUCKETS)
+    }
+
+    /// How many tokens were sent in the window ending at `now`.
+    pub(crate) fn spent(&self, now: u64) -> Balance {
+        self.live_buckets(now).map(|bucket| bucket.amount).sum()
+    }
+
+    /// The block timestamp at which the oldest spend that still counts leaves the window. `None` if nothing counts.
+    pub(crate) fn next_release_at(&self, now: u64) -> Option<u64> {
+        self.live_buckets(now)
+            .map(|bucket| (bucket.hour + DAILY_LIMIT_BUCKETS) * DAILY_LIMIT_BUCKET)
+            .min()
+    }
+
+    /// The amount of tokens that can still be sent at `now`. `None` means there is no limit.
+    pub(crate) fn remaining(&self, now: u64) -> Option<Balance> {
+        self.limit.map(|limit| limit.saturating_sub(self.spent(now)))
+    }
+
+    /// Adds a spend to the bucket of the current hour. The bucket is reset first if it still holds an hour that left the window.
+    pub(crate) fn record_spend(&mut self, now: u64, amount: Balance) {
+        let current_hour = now / DAILY_LIMIT_BUCKET;
+        let bucket = &mut self.buckets[(current_hour % DAILY_LIMIT_BUCKETS) as usize];
+        if bucket.hour != current_hour {
+            *bucket = SpendBucket { hour: current_hour, amount: 0 };
+        }
+        bucket.amount += amount;
+    }
+}
+
+/// The daily limit of an account as it's returned from the view methods
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DailyLimitView {
+    pub limit: Option<U128>, // The limit currently in effect. `null` means there is no limit.
+    pub pending_limit: Option<U128>, // A raised limit that hasn't taken effect yet
+    pub pending_limit_removal: bool, // Whether the limit is scheduled to be removed
+    pub pending_limit_effective_at: Option<U64>, // The block timestamp at which the pending limit takes effect
+    pub spent_in_window: U128, // How many tokens were sent in the last 24 hours
+    pub next_release_at: Option<U64>, // The block timestamp at which the oldest of those spends stops counting. `null` if nothing was sent.
+    pub remaining: Option<U128>, // How many tokens can still be sent right now. `null` means there is no limit.
+}
+
+impl Contract {
+    /// Internal method for spending some amount from an account's daily allowance. If the account has no limit, this does nothing.
+    /// If the amount exceeds the remaining allowance, panic.
+    ///
+    /// Only transfers to other accounts are counted. Staking, funding rewards and the refund in `ft_resolve_transfer`
+    /// don't send tokens away, so they withdraw without spending the allowance.
+    pub(crate) fn internal_spend_daily_allowance(&mut self, account_id: &AccountId, amount: Balance) {
+        // Get the account's limit. If they didn't opt into one, there's nothing to enforce.
+        let mut daily_limit = match self.daily_limits.get(account_id) {
+            Some(daily_limit) => daily_limit,
+            None => return,
+        };
+        let now = env::block_timestamp();
+        daily_limit.refresh(now);
+
+        // Ensure the amount fits in what's left of the allowance
+        if let Some(remaining) = daily_limit.remaining(now) {
+            require!(
+                amount <= remaining,
+                format!("The transfer exceeds the daily transfer limit. Remaining allowance: {}", remaining)
+            );
+        }
+
+        daily_limit.record_spend(now, amount);
+        self.daily_limits.insert(account_id, &daily_limit);
+    }
+
+    /// Internal method for getting the up to date daily limit of an account.
+    pub(crate) fn internal_get_daily_limit(&self, account_id: &AccountId) -> Option<DailyLimit> {
+        self.daily_limits.get(account_id).map(|mut daily_limit| {
+            daily_limit.refresh(env::block_timestamp());
+            daily_limit
+        })
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets a limit on how many tokens the caller can send within any 24 hour window. Passing `null` removes the limit.
+    ///
+    /// Lowering the limit takes effect immediately. Raising or removing the limit only takes effect after
+    /// `DAILY_LIMIT_TIMELOCK` has passed, so that a compromised key can't lift the limit and drain the account right away.
+    ///
+    /// The first time an account sets a limit, it must attach enough $NEAR to cover the storage of the limit. Any
+    /// excess is refunded. Afterwards, exactly 1 yoctoNEAR must be attached.
+    #[payable]
+    pub fn ft_set_daily_limit(&mut self, limit: Option<U128>) {
+        let account_id = env::predecessor_account_id();
+        // Ensure the account is registered
+        self.internal_unwrap_balance_of(&account_id);
+        let limit: Option<Balance> = limit.map(|limit| limit.0);
+        let now = env::block_timestamp();
+
+        match self.internal_get_daily_limit(&account_id) {
+            Some(mut daily_limit) => {
+                // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
+                assert_one_yocto();
+
+                // The new limit is lower if the current limit is removed or bigger than the new one
+                let is_lower = match (daily_limit.limit, limit) {
+                    (None, _) => true,
+                    (Some(_), None) => false,
+                    (Some(current), Some(new)) => new <= current,
+                };
+
+                if is_lower {
+                    // Lowering the limit applies immediately and cancels any pending raise
+                    daily_limit.limit = limit;
+                    daily_limit.pending = None;
+                } else {
+                    // Raising the limit only applies once the timelock has passed
+                    daily_limit.pending = Some(PendingDailyLimit {
+                        limit,
+                        effective_at: now + DAILY_LIMIT_TIMELOCK,
+                    });
+                }
+                self.daily_limits.insert(&account_id, &daily_limit);
+            }
+            None => {
+                // Opting into a limit always lowers it so it applies immediately
+                let initial_storage_usage = env::storage_usage();
+                self.daily_limits.insert(&account_id, &DailyLimit::new(limit));
+
+                // Charge the caller for the storage of the limit and refund any excess $NEAR
+                self.internal_settle_storage_change(initial_storage_usage);
+            }
+        }
+    }
+
+    /// Returns the daily limit of an account, or `null` if the account never set one.
+    pub fn ft_daily_limit_of(&self, account_id: AccountId) -> Option<DailyLimitView> {
+        let now = env::block_timestamp();
+        self.internal_get_daily_limit(&account_id).map(|daily_limit| DailyLimitView {
+            limit: daily_limit.limit.map(U128),
+            pending_limit: daily_limit.pending.as_ref().and_then(|pending| pending.limit.map(U128)),
+            pending_limit_removal: daily_limit.pending.as_ref().is_some_and(|pending| pending.limit.is_none()),
+            pending_limit_effective_at: daily_limit.pending.as_ref().map(|pending| U64(pending.effective_at)),
+            spent_in_window: U128(daily_limit.spent(now)),
+            next_release_at: daily_limit.next_release_at(now).map(U64),
+            remaining: daily_limit.remaining(now).map(U128),
+        })
+    }
+
+    /// Returns how many tokens an account can still send right now, or `null` if it has no limit.
+    pub fn ft_remaining_daily_allowance(&self, account_id: AccountId) -> Option<U128> {
+        self.internal_get_daily_limit(&account_id)
+            .and_then(|daily_limit| daily_limit.remaining(env::block_timestamp()))
+            .map(U128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = DAILY_LIMIT_BUCKET;
+
+    #[test]
+    fn spends_count_for_at_least_a_full_window() {
+        let mut daily_limit = DailyLimit::new(Some(100));
+        let spent_at = 10 * HOUR + HOUR / 2;
+        daily_limit.record_spend(spent_at, 100);
+
+        // Right before 24 hours have passed the whole limit is still used up
+        assert_eq!(daily_limit.remaining(spent_at + DAILY_LIMIT_WINDOW - 1), Some(0));
+        // The spend is released once its whole hour has left the window
+        let released_at = daily_limit.next_release_at(spent_at).unwrap();
+        assert_eq!(released_at, 35 * HOUR);
+        assert!(released_at >= spent_at + DAILY_LIMIT_WINDOW);
+        assert_eq!(daily_limit.remaining(released_at - 1), Some(0));
+        assert_eq!(daily_limit.remaining(released_at), Some(100));
+        assert_eq!(daily_limit.next_release_at(released_at), None);
+    }
+
+    #[test]
+    fn window_slides_instead_of_resetting() {
+        let mut daily_limit = DailyLimit::new(Some(100));
+        daily_limit.record_spend(0, 60);
+        daily_limit.record_spend(12 * HOUR, 40);
+        assert_eq!(daily_limit.remaining(12 * HOUR), Some(0));
+
+        // Only the first spend has left the window, so the second one still counts
+        assert_eq!(daily_limit.remaining(25 * HOUR), Some(60));
+        assert_eq!(daily_limit.next_release_at(25 * HOUR), Some(37 * HOUR));
+        assert_eq!(daily_limit.remaining(37 * HOUR), Some(100));
+    }
+
+    #[test]
+    fn reused_bucket_drops_the_old_hour() {
+        let mut daily_limit = DailyLimit::new(Some(100));
+        daily_limit.record_spend(HOUR, 70);
+        // This hour maps to the same bucket as the first spend, which has left the window
+        let later = (1 + DAILY_LIMIT_BUCKETS) * HOUR;
+        daily_limit.record_spend(later, 30);
+        assert_eq!(daily_limit.spent(later), 30);
+        assert_eq!(daily_limit.buckets.len() as u64, DAILY_LIMIT_BUCKETS);
+    }
+}
@@ -0,0 +1,106 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require};
+
+use crate::*;
+
+/// The default maximum length of a transfer memo in bytes
+pub const DEFAULT_MAX_MEMO_LEN: u32 = 256;
+
+/// The default maximum length of an `ft_transfer_call` msg in bytes
+pub const DEFAULT_MAX_MSG_LEN: u32 = 4096;
+
+/// The highest maximum memo length the owner can set. Memos are copied into every transfer event.
+pub const MAX_MEMO_LEN_LIMIT: u32 = 1024;
+
+/// The highest maximum msg length the owner can set. Msgs are copied into every `ft_on_transfer` call.
+pub const MAX_MSG_LEN_LIMIT: u32 = 16 * 1024;
+
+/// The prefix that marks a memo as structured. Memos without it are plain text.
+pub const STRUCTURED_MEMO_PREFIX: &str = "memo-v1:";
+
+/// The maximum lengths of the free form strings that are copied into event logs and cross-contract calls
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MessageLimits {
+    pub max_memo_len: u32, // The maximum length of a transfer memo in bytes
+    pub max_msg_len: u32, // The maximum length of an `ft_transfer_call` msg in bytes
+}
+
+impl Default for MessageLimits {
+    fn default() -> Self {
+        Self {
+            max_memo_len: DEFAULT_MAX_MEMO_LEN,
+            max_msg_len: DEFAULT_MAX_MSG_LEN,
+        }
+    }
+}
+
+/// A machine-readable memo. Structured memos are opted into with [`STRUCTURED_MEMO_PREFIX`], followed by a JSON
+/// object with exactly these fields. The memo is validated but otherwise passed through to the transfer event unchanged.
+///
+/// Example: `memo-v1:{"type":"invoice","reference":"INV-2022-0042"}`
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(deny_unknown_fields)]
+pub struct StructuredMemo {
+    #[serde(rename = "type")]
+    pub memo_type: String, // The kind of payment, such as "invoice"
+    pub reference: String, // The payment reference, such as an invoice number
+}
+
+impl Contract {
+    /// Internal method for ensuring that a memo isn't too long and, if it's structured, that it's valid. If not, panic.
+    pub(crate) fn assert_valid_memo(&self, memo: Option<&str>) {
+        let memo = match memo {
+            Some(memo) => memo,
+            None => return,
+        };
+
+        require!(
+            memo.len() <= self.message_limits.max_memo_len as usize,
+            format!("The memo can't be longer than {} bytes", self.message_limits.max_memo_len)
+        );
+
+        // Memos that opt into the structured memo format must follow it. Any other memo is plain text.
+        if let Some(json) = memo.strip_prefix(STRUCTURED_MEMO_PREFIX) {
+            let structured: StructuredMemo = near_sdk::serde_json::from_str(json)
+                .unwrap_or_else(|err| env::panic_str(format!("Invalid structured memo: {}", err).as_str()));
+            require!(
+                !structured.memo_type.is_empty() && !structured.reference.is_empty(),
+                "Invalid structured memo: type and reference can't be empty"
+            );
+        }
+    }
+
+    /// Internal method for ensuring that an `ft_transfer_call` msg isn't too long. If it is, panic.
+    pub(crate) fn assert_valid_msg(&self, msg: &str) {
+        require!(
+            msg.len() <= self.message_limits.max_msg_len as usize,
+            format!("The msg can't be longer than {} bytes", self.message_limits.max_msg_len)
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the maximum lengths of transfer memos and `ft_transfer_call` msgs. They can't be higher than
+    /// `MAX_MEMO_LEN_LIMIT` and `MAX_MSG_LEN_LIMIT`. Only the owner can call this method.
+    pub fn set_message_limits(&mut self, message_limits: MessageLimits) {
+        self.assert_owner();
+        require!(
+            message_limits.max_memo_len <= MAX_MEMO_LEN_LIMIT,
+            format!("The maximum memo length can't be more than {} bytes", MAX_MEMO_LEN_LIMIT)
+        );
+        require!(
+            message_limits.max_msg_len <= MAX_MSG_LEN_LIMIT,
+            format!("The maximum msg length can't be more than {} bytes", MAX_MSG_LEN_LIMIT)
+        );
+        self.message_limits = message_limits;
+    }
+
+    /// Returns the maximum lengths of transfer memos and `ft_transfer_call` msgs.
+    pub fn ft_message_limits(&self) -> MessageLimits {
+        self.message_limits.clone()
+    }
+}
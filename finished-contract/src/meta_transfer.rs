@@ -0,0 +1,171 @@
+use ed25519_dalek::Verifier;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, CurveType, PublicKey};
+
+use crate::*;
+
+/// The maximum number of keys an account can register for signing transfer intents
+pub const MAX_SIGNER_KEYS_PER_ACCOUNT: usize = 10;
+
+/// A transfer signed off-chain by the sender that any relayer can submit through `ft_transfer_signed`.
+///
+/// The signature is an ed25519 signature over the Borsh serialization of `(contract_id, intent)` where
+/// `contract_id` is the account ID of this contract. This prevents an intent from being replayed on another token.
+#[derive(BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferIntent {
+    pub sender_id: AccountId, // The account the tokens are sent from. This account signed the intent.
+    pub receiver_id: AccountId, // The account the tokens are sent to
+    pub amount: U128, // The amount of tokens to transfer
+    pub memo: Option<String>, // An optional memo for the transfer
+    pub nonce: U64, // Must be greater than the last nonce used by the sender
+    pub expires_at: U64, // The block timestamp (in nanoseconds) after which the intent can't be submitted anymore
+    pub relayer_id: Option<AccountId>, // If set, only this account can submit the intent
+    pub relayer_fee: Option<U128>, // Tokens paid from the sender to the relayer for submitting the intent
+}
+
+/// The keys an account registered for signing transfer intents and the last nonce it used
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct SignerAccount {
+    /// The ed25519 keys that can sign transfer intents for the account
+    pub public_keys: Vec<PublicKey>,
+    /// The last nonce used in a transfer intent. The next intent must use a greater nonce.
+    pub last_nonce: u64,
+}
+
+impl Contract {
+    /// Internal method for verifying that `signature` is a valid signature of `intent` by `public_key`.
+    ///
+    /// near-sdk 4 has no host function for ed25519 (`env::ed25519_verify` needs near-sdk 5 and protocol version 59),
+    /// so the signature is verified in wasm with `ed25519-dalek`. This costs far more gas than the transfer itself.
+    fn internal_verify_intent_signature(&self, intent: &TransferIntent, public_key: &PublicKey, signature: &[u8]) {
+        let message = (env::current_account_id(), intent).try_to_vec().unwrap();
+
+        // The first byte of a public key is its curve type which we need to strip
+        let public_key = ed25519_dalek::PublicKey::from_bytes(&public_key.as_bytes()[1..])
+            .unwrap_or_else(|_| env::panic_str("Invalid public key"));
+        let signature = ed25519_dalek::Signature::try_from(signature)
+            .unwrap_or_else(|_| env::panic_str("Invalid signature"));
+
+        require!(public_key.verify(&message, &signature).is_ok(), "Invalid signature");
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers an ed25519 public key that can sign transfer intents on behalf of the caller.
+    ///
+    /// The caller must attach enough $NEAR to cover the storage of the key. Any excess is refunded.
+    #[payable]
+    pub fn ft_add_signer_key(&mut self, public_key: PublicKey) {
+        let account_id = env::predecessor_account_id();
+        // Ensure the account is registered
+        self.internal_unwrap_balance_of(&account_id);
+        require!(public_key.curve_type() == CurveType::ED25519, "Only ed25519 keys are supported");
+
+        let mut signer = self.signer_accounts.get(&account_id).unwrap_or_default();
+        require!(!signer.public_keys.contains(&public_key), "The key is already registered");
+        require!(
+            signer.public_keys.len() < MAX_SIGNER_KEYS_PER_ACCOUNT,
+            format!("An account can't register more than {} keys", MAX_SIGNER_KEYS_PER_ACCOUNT)
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        signer.public_keys.push(public_key);
+        self.signer_accounts.insert(&account_id, &signer);
+
+        // Charge the caller for the storage of the key and refund any excess $NEAR
+        self.internal_settle_storage_change(initial_storage_usage);
+    }
+
+    /// Removes a key that was registered with `ft_add_signer_key` and refunds the storage it took up.
+    /// Exactly 1 yoctoNEAR must be attached.
+    ///
+    /// The account's nonce is kept so that intents signed before the key was removed can't be replayed if it's added again.
+    #[payable]
+    pub fn ft_remove_signer_key(&mut self, public_key: PublicKey) {
+        // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+
+        let mut signer = self.signer_accounts.get(&account_id).unwrap_or_default();
+        let index = signer
+            .public_keys
+            .iter()
+            .position(|key| key == &public_key)
+            .unwrap_or_else(|| env::panic_str("The key is not registered"));
+
+        let initial_storage_usage = env::storage_usage();
+        signer.public_keys.remove(index);
+        self.signer_accounts.insert(&account_id, &signer);
+
+        // Refund the storage that was freed up along with the deposit
+        self.internal_settle_storage_change(initial_storage_usage);
+    }
+
+    /// Executes a transfer that was signed off-chain by the sender. This can be submitted by any account (the relayer)
+    /// so that the sender doesn't need $NEAR to pay for gas.
+    ///
+    /// Arguments:
+    /// - `intent` - the transfer to execute. See [`TransferIntent`].
+    /// - `public_key` - the key that signed the intent. It must have been registered by the sender with `ft_add_signer_key`.
+    /// - `signature` - the base64-encoded ed25519 signature of the intent.
+    ///
+    /// If the intent contains a relayer fee, it's transferred from the sender to the caller after the transfer itself,
+    /// unless the caller is the sender.
+    ///
+    /// The signature is verified in wasm rather than by the host, so relayers should attach the maximum of 300 Tgas.
+    pub fn ft_transfer_signed(&mut self, intent: TransferIntent, public_key: PublicKey, signature: Base64VecU8) {
+        let relayer_id = env::predecessor_account_id();
+
+        // Ensure the intent can still be submitted and, if it's bound to a relayer, that the caller is that relayer
+        require!(env::block_timestamp() <= intent.expires_at.0, "The transfer intent has expired");
+        if let Some(intent_relayer_id) = &intent.relayer_id {
+            require!(intent_relayer_id == &relayer_id, "The transfer intent is bound to a different relayer");
+        }
+
+        // Ensure the memo is valid before changing any state
+        self.assert_valid_memo(intent.memo.as_deref());
+
+        // Ensure the key belongs to the sender and that it signed the intent
+        let mut signer = self
+            .signer_accounts
+            .get(&intent.sender_id)
+            .unwrap_or_else(|| env::panic_str("The sender has no registered keys"));
+        require!(signer.public_keys.contains(&public_key), "The key is not registered for the sender");
+        self.internal_verify_intent_signature(&intent, &public_key, &signature.0);
+
+        // Ensure the nonce wasn't used before and bump it so that the intent can't be replayed
+        require!(intent.nonce.0 > signer.last_nonce, "The nonce was already used");
+        signer.last_nonce = intent.nonce.0;
+        self.signer_accounts.insert(&intent.sender_id, &signer);
+
+        // Transfer the tokens
+        self.internal_transfer(&intent.sender_id, &intent.receiver_id, intent.amount.into(), intent.memo);
+
+        // Pay the relayer for submitting the intent. A sender that submits their own intent doesn't pay themselves.
+        let relayer_fee: Balance = intent.relayer_fee.map(|fee| fee.0).unwrap_or(0);
+        if relayer_fee > 0 && relayer_id != intent.sender_id {
+            self.internal_transfer(&intent.sender_id, &relayer_id, relayer_fee, Some("Relayer fee".to_string()));
+        }
+    }
+
+    /// Returns the keys an account registered for signing transfer intents.
+    pub fn ft_signer_keys_of(&self, account_id: AccountId) -> Vec<PublicKey> {
+        self.signer_accounts
+            .get(&account_id)
+            .map(|signer| signer.public_keys)
+            .unwrap_or_default()
+    }
+
+    /// Returns the last nonce an account used in a transfer intent. The next intent must use a greater nonce.
+    pub fn ft_signer_nonce_of(&self, account_id: AccountId) -> U64 {
+        self.signer_accounts
+            .get(&account_id)
+            .map(|signer| signer.last_nonce)
+            .unwrap_or(0)
+            .into()
+    }
+}
@@ -1,7 +1,7 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::Base64VecU8;
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{near_bindgen};
+use near_sdk::{env, near_bindgen, require};
 
 use crate::*;
 
@@ -17,14 +17,186 @@ pub struct FungibleTokenMetadata {
     pub decimals: u8, // used in frontends to show the proper significant digits of a token. This concept is explained well in this OpenZeppelin post. https://docs.openzeppelin.com/contracts/3.x/erc20#a-note-on-decimals
 }
 
+/// The metadata returned by `ft_metadata`. This is the standard metadata, with a flag for when the icon was left out.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FungibleTokenMetadataView {
+    #[serde(flatten)]
+    pub metadata: FungibleTokenMetadata, // The standard metadata of the token
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub icon_omitted: bool, // Set when the token has an icon that was left out. Use `ft_icon` to get it.
+}
+
 pub trait FungibleTokenMetadataProvider {
-    // View call for returning the contract metadata
-    fn ft_metadata(&self) -> FungibleTokenMetadata;
+    // View call for returning the contract metadata. The icon is included unless `include_icon` is false.
+    fn ft_metadata(&self, include_icon: Option<bool>) -> FungibleTokenMetadataView;
 }
 
 #[near_bindgen]
 impl FungibleTokenMetadataProvider for Contract {
-    fn ft_metadata(&self) -> FungibleTokenMetadata {
-        self.metadata.get().unwrap()
+    fn ft_metadata(&self, include_icon: Option<bool>) -> FungibleTokenMetadataView {
+        // The icon isn't stored with the rest of the metadata so we only read it when it's needed
+        let mut metadata = self.metadata.get().unwrap();
+        let mut icon_omitted = false;
+        if include_icon.unwrap_or(true) {
+            metadata.icon = self.ft_icon();
+        } else {
+            icon_omitted = !self.icon_chunks.is_empty();
+        }
+        FungibleTokenMetadataView { metadata, icon_omitted }
+    }
+}
+
+/// The number of bytes stored in each chunk of the icon
+pub const ICON_CHUNK_LEN: usize = 2048;
+
+/// The default maximum size of the icon in bytes
+pub const DEFAULT_MAX_ICON_LEN: u32 = 16 * 1024;
+
+impl Contract {
+    /// Internal method for storing the icon in chunks, separately from the rest of the metadata. If the icon is
+    /// larger than the maximum icon size, panic.
+    pub(crate) fn internal_set_icon(&mut self, icon: Option<String>) {
+        let icon = icon.unwrap_or_default();
+        require!(
+            icon.len() <= self.max_icon_len as usize,
+            format!("The icon can't be larger than {} bytes", self.max_icon_len)
+        );
+
+        self.icon_chunks.clear();
+        let mut rest = icon.as_str();
+        while !rest.is_empty() {
+            // Split at a character boundary so that every chunk is a valid string
+            let mut end = std::cmp::min(rest.len(), ICON_CHUNK_LEN);
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            let (chunk, remaining) = rest.split_at(end);
+            self.icon_chunks.push(&chunk.to_string());
+            rest = remaining;
+        }
+    }
+}
+
+/// The maximum size in bytes of the reference document that can be stored on chain
+pub const MAX_REFERENCE_LEN: usize = 16 * 1024;
+
+impl Contract {
+    /// Internal method for storing the reference document. If it's too large or not valid JSON, panic.
+    fn internal_store_metadata_reference(&mut self, document: Vec<u8>) {
+        require!(
+            document.len() <= MAX_REFERENCE_LEN,
+            format!("The reference document can't be larger than {} bytes", MAX_REFERENCE_LEN)
+        );
+        // Ensure the document is valid JSON
+        near_sdk::serde_json::from_slice::<near_sdk::serde_json::Value>(&document)
+            .unwrap_or_else(|_| env::panic_str("The reference document is not valid JSON"));
+
+        self.metadata_reference.set(&document);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Stores the JSON document that the metadata's `reference` points to, so that it can be read and verified on chain.
+    /// Only the owner can call this method.
+    ///
+    /// The sha256 hash of the document must match the metadata's `reference_hash`. If no `reference_hash` is set,
+    /// it's set to the hash of the document. To point the metadata at a different document, use
+    /// `update_metadata_reference` instead.
+    ///
+    /// The caller must attach enough $NEAR to cover any extra storage. Any excess, or storage that was freed up by
+    /// replacing a larger document, is refunded.
+    #[payable]
+    pub fn set_metadata_reference(&mut self, reference_json: Base64VecU8) {
+        self.assert_owner();
+        let document = reference_json.0;
+        // Keep track of how much the storage changes
+        let initial_storage_usage = env::storage_usage();
+
+        // Ensure the hash of the document matches the metadata, or set it if the metadata has no hash yet
+        let hash = env::sha256(&document);
+        let mut metadata = self.metadata.get().unwrap();
+        match &metadata.reference_hash {
+            Some(reference_hash) => {
+                require!(reference_hash.0 == hash, "The reference document doesn't match the reference_hash")
+            }
+            None => {
+                metadata.reference_hash = Some(hash.into());
+                self.metadata.set(&metadata);
+            }
+        }
+
+        // Store the document and charge the caller for the storage
+        self.internal_store_metadata_reference(document);
+        self.internal_settle_storage_change(initial_storage_usage);
+    }
+
+    /// Points the metadata at a new reference document. The metadata's `reference` is replaced with `reference`, its
+    /// `reference_hash` with the sha256 hash of the document, and the document is stored on chain. Only the owner can
+    /// call this method.
+    ///
+    /// Emits an `ft_metadata` `metadata_reference_update` event. The caller must attach enough $NEAR to cover any extra
+    /// storage. Any excess, or storage that was freed up by replacing a larger document, is refunded.
+    #[payable]
+    pub fn update_metadata_reference(&mut self, reference: String, reference_json: Base64VecU8) {
+        self.assert_owner();
+        let document = reference_json.0;
+        // Keep track of how much the storage changes
+        let initial_storage_usage = env::storage_usage();
+
+        // Replace the reference and its hash together so that they never point at different documents
+        let reference_hash: Base64VecU8 = env::sha256(&document).into();
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.reference = Some(reference.clone());
+        metadata.reference_hash = Some(reference_hash.clone());
+        self.metadata.set(&metadata);
+
+        // Store the document and charge the caller for the storage
+        self.internal_store_metadata_reference(document);
+        self.internal_settle_storage_change(initial_storage_usage);
+
+        FtMetadataReferenceUpdate {
+            reference: &reference,
+            reference_hash: &reference_hash,
+            updated_by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    /// Returns the reference document stored with `set_metadata_reference`. Its sha256 hash matches the metadata's `reference_hash`.
+    pub fn ft_metadata_reference(&self) -> Option<Base64VecU8> {
+        self.metadata_reference.get().map(Base64VecU8)
+    }
+
+    /// Replaces the icon of the token. Only the owner can call this method.
+    ///
+    /// The icon can't be larger than `ft_max_icon_len`. The caller must attach enough $NEAR to cover any extra
+    /// storage. Any excess, or storage that was freed up by a smaller icon, is refunded.
+    #[payable]
+    pub fn set_icon(&mut self, icon: Option<String>) {
+        self.assert_owner();
+        let initial_storage_usage = env::storage_usage();
+        self.internal_set_icon(icon);
+        self.internal_settle_storage_change(initial_storage_usage);
+    }
+
+    /// Sets the maximum size of the icon in bytes. This only applies to icons set afterwards. Only the owner can call this method.
+    pub fn set_max_icon_len(&mut self, max_icon_len: u32) {
+        self.assert_owner();
+        self.max_icon_len = max_icon_len;
+    }
+
+    /// Returns the icon of the token without the rest of the metadata.
+    pub fn ft_icon(&self) -> Option<String> {
+        if self.icon_chunks.is_empty() {
+            return None;
+        }
+        Some(self.icon_chunks.iter().collect())
+    }
+
+    /// Returns the maximum size of the icon in bytes.
+    pub fn ft_max_icon_len(&self) -> u32 {
+        self.max_icon_len
     }
 }
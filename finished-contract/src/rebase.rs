@@ -0,0 +1,237 @@
+//! Share-based accounting for rebasing (yield-bearing) tokens.
+//!
+//! In rebasing mode, `accounts` and `total_supply` hold shares instead of token amounts. The token amount of a number
+//! of shares is `shares * index / INDEX_PRECISION`, so raising the index distributes yield to every holder and lowering
+//! it socializes a loss. All public methods and events still deal in token amounts.
+//!
+//! Rounding:
+//! - Token amounts are converted to shares rounding up, so a transfer of `amount` never moves less than `amount`
+//!   worth of shares. The sender and the receiver always use the same number of shares, so the total is conserved.
+//! - Shares are converted to token amounts rounding down, so balances and the total supply never overstate what can
+//!   be transferred. Because of this, the balances of the sender and the receiver can change by up to the value of one
+//!   share more than `amount`.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{env, log, near_bindgen, require, AccountId, Balance};
+
+use crate::*;
+
+// The code generated by `construct_uint!` doesn't follow every clippy lint
+#[allow(clippy::all)]
+mod u256 {
+    uint::construct_uint! {
+        /// A 256-bit unsigned integer so that shares can be multiplied by the index without overflowing
+        pub struct U256(4);
+    }
+}
+pub use u256::U256;
+
+/// The index that represents a 1:1 ratio between shares and token amounts
+pub const INDEX_PRECISION: Balance = 1_000_000_000_000_000_000_000_000;
+
+/// The state of a rebasing deployment
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Rebasing {
+    /// The token amount of `INDEX_PRECISION` shares
+    pub index: Balance,
+    /// An account that can update the index alongside the owner
+    pub oracle_id: Option<AccountId>,
+}
+
+impl Default for Rebasing {
+    fn default() -> Self {
+        Self {
+            index: INDEX_PRECISION,
+            oracle_id: None,
+        }
+    }
+}
+
+/// Internal function for calculating `value * numerator / denominator` without overflowing, rounding down or up.
+pub(crate) fn mul_div(value: Balance, numerator: Balance, denominator: Balance, round_up: bool) -> Balance {
+    let product = U256::from(value) * U256::from(numerator);
+    let denominator = U256::from(denominator);
+    let mut result = product / denominator;
+    if round_up && !(product % denominator).is_zero() {
+        result += U256::one();
+    }
+    require!(result <= U256::from(Balance::MAX), "Balance overflow");
+    result.as_u128()
+}
+
+impl Contract {
+    /// Internal method for converting a token amount to shares, rounding up. Without rebasing, this is the amount itself.
+    pub(crate) fn internal_amount_to_shares(&self, amount: Balance) -> Balance {
+        match &self.rebasing {
+            Some(rebasing) => mul_div(amount, INDEX_PRECISION, rebasing.index, true),
+            None => amount,
+        }
+    }
+
+    /// Internal method for converting shares to a token amount, rounding down. Without rebasing, this is the shares themselves.
+    pub(crate) fn internal_shares_to_amount(&self, shares: Balance) -> Balance {
+        match &self.rebasing {
+            Some(rebasing) => mul_div(shares, rebasing.index, INDEX_PRECISION, false),
+            None => shares,
+        }
+    }
+
+    /// Internal method for getting the rebasing state. If the contract isn't rebasing, panic.
+    fn internal_unwrap_rebasing(&mut self) -> &mut Rebasing {
+        self.rebasing
+            .as_mut()
+            .unwrap_or_else(|| env::panic_str("The contract is not rebasing"))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account that can update the index alongside the owner. Pass `null` to remove it.
+    /// Only the owner can call this method.
+    pub fn set_rebase_oracle(&mut self, oracle_id: Option<AccountId>) {
+        self.assert_owner();
+        self.internal_unwrap_rebasing().oracle_id = oracle_id;
+    }
+
+    /// Sets the index that converts shares to token amounts. Raising it distributes yield to every holder and
+    /// lowering it socializes a loss. Only the owner or the oracle can call this method.
+    pub fn set_rebase_index(&mut self, index: U128) {
+        let predecessor_id = env::predecessor_account_id();
+        let owner_id = self.owner_id.clone();
+        let rebasing = self.internal_unwrap_rebasing();
+        require!(
+            predecessor_id == owner_id || rebasing.oracle_id.as_ref() == Some(&predecessor_id),
+            "Only the owner or the oracle can update the index"
+        );
+        require!(index.0 > 0, "The index must be positive");
+
+        log!("Rebasing index updated from {} to {}", rebasing.index, index.0);
+        rebasing.index = index.0;
+    }
+
+    /// Returns whether balances are tracked as shares that rebase with an index.
+    pub fn ft_is_rebasing(&self) -> bool {
+        self.rebasing.is_some()
+    }
+
+    /// Returns the index that converts shares to token amounts, or `null` if the contract isn't rebasing.
+    /// `INDEX_PRECISION` (10^24) means that one share is worth one token unit.
+    pub fn ft_rebase_index(&self) -> Option<U128> {
+        self.rebasing.as_ref().map(|rebasing| rebasing.index.into())
+    }
+
+    /// Returns the account that can update the index alongside the owner, if any.
+    pub fn ft_rebase_oracle(&self) -> Option<AccountId> {
+        self.rebasing.as_ref().and_then(|rebasing| rebasing.oracle_id.clone())
+    }
+
+    /// Returns the shares of an account. Without rebasing, this is the same as `ft_balance_of`.
+    pub fn ft_shares_of(&self, account_id: AccountId) -> U128 {
+        self.internal_get_account(&account_id)
+            .map(|account| account.balance)
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Returns the total shares of all accounts. Without rebasing, this is the same as `ft_total_supply`.
+    pub fn ft_total_shares(&self) -> U128 {
+        self.total_supply.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::ft_core::FungibleTokenCore;
+
+    fn owner() -> AccountId {
+        AccountId::new_unchecked("owner.near".to_string())
+    }
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice.near".to_string())
+    }
+
+    fn metadata() -> FungibleTokenMetadata {
+        near_sdk::serde_json::from_str(r#"{"spec":"ft-1.0.0","name":"Token","symbol":"TKN","decimals":24}"#).unwrap()
+    }
+
+    fn set_predecessor(predecessor_id: AccountId) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(AccountId::new_unchecked("ft.near".to_string()))
+            .predecessor_account_id(predecessor_id)
+            .build());
+    }
+
+    // Creates a rebasing token where the owner holds 1,000 shares and the index is 1.5
+    fn setup() -> Contract {
+        set_predecessor(owner());
+        let mut contract = Contract::new(owner(), U128(1_000), metadata(), None, Some(true));
+        contract.internal_register_account(&alice(), 0);
+        contract.set_rebase_index(U128(INDEX_PRECISION * 3 / 2));
+        contract
+    }
+
+    #[test]
+    fn amounts_round_up_and_shares_round_down() {
+        let contract = setup();
+        assert_eq!(contract.internal_amount_to_shares(1), 1);
+        assert_eq!(contract.internal_amount_to_shares(10), 7);
+        assert_eq!(contract.internal_amount_to_shares(9), 6);
+        assert_eq!(contract.internal_shares_to_amount(1), 1);
+        assert_eq!(contract.internal_shares_to_amount(7), 10);
+        assert_eq!(contract.internal_shares_to_amount(6), 9);
+    }
+
+    #[test]
+    fn index_changes_rebase_every_balance() {
+        let mut contract = setup();
+        assert_eq!(contract.ft_balance_of(owner()), U128(1_500));
+        assert_eq!(contract.ft_total_supply(), U128(1_500));
+
+        // Lowering the index socializes a loss without touching the shares
+        contract.set_rebase_index(U128(INDEX_PRECISION / 2));
+        assert_eq!(contract.ft_balance_of(owner()), U128(500));
+        assert_eq!(contract.ft_total_supply(), U128(500));
+        assert_eq!(contract.ft_shares_of(owner()), U128(1_000));
+        assert_eq!(contract.ft_total_shares(), U128(1_000));
+    }
+
+    #[test]
+    fn transfers_move_the_same_shares_on_both_sides() {
+        let mut contract = setup();
+        contract.internal_transfer(&owner(), &alice(), 10, None);
+
+        // 10 tokens are worth 6.67 shares, which rounds up to 7
+        assert_eq!(contract.ft_shares_of(alice()), U128(7));
+        assert_eq!(contract.ft_shares_of(owner()), U128(993));
+        assert_eq!(contract.ft_total_shares(), U128(1_000));
+        // The receiver gets at least the amount and the sender loses up to one share more
+        assert_eq!(contract.ft_balance_of(alice()), U128(10));
+        assert_eq!(contract.ft_balance_of(owner()), U128(1_489));
+    }
+
+    #[test]
+    fn oracle_can_update_the_index() {
+        let mut contract = setup();
+        let oracle_id = AccountId::new_unchecked("oracle.near".to_string());
+        contract.set_rebase_oracle(Some(oracle_id.clone()));
+
+        set_predecessor(oracle_id);
+        contract.set_rebase_index(U128(INDEX_PRECISION * 2));
+        assert_eq!(contract.ft_rebase_index(), Some(U128(INDEX_PRECISION * 2)));
+        assert_eq!(contract.ft_balance_of(owner()), U128(2_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner or the oracle can update the index")]
+    fn other_accounts_cannot_update_the_index() {
+        let mut contract = setup();
+        set_predecessor(alice());
+        contract.set_rebase_index(U128(INDEX_PRECISION * 2));
+    }
+}
@@ -0,0 +1,647 @@
+//! Staking the token to earn more of it over time.
+//!
+//! Staked tokens leave the holder's balance so they can't be transferred, but they still count in `total_supply`.
+//! Rewards accrue at the configured APR using reward-per-token accounting: a global accumulator grows by
+//! `apr_bps / 10_000` tokens per staked token per year, and every stake remembers the accumulator value it was last
+//! settled at. Rewards are either minted or paid out of a reserve the owner funds with `ft_fund_staking_rewards`. The
+//! owner can take back the part of the reserve that isn't owed to stakers with `ft_defund_staking_rewards`.
+//!
+//! Unstaked tokens go through an unbonding delay measured in epochs before they can be withdrawn back to the balance.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, EpochHeight};
+
+use crate::rebase::mul_div;
+use crate::*;
+
+/// The precision of the reward-per-token accumulator
+pub const REWARD_PER_TOKEN_PRECISION: Balance = 1_000_000_000_000_000_000_000_000;
+
+/// The denominator of the APR in basis points
+pub const APR_BPS_DENOMINATOR: u32 = 10_000;
+
+/// The highest APR the owner can set, in basis points (100%)
+pub const MAX_APR_BPS: u32 = 10_000;
+
+/// The length of a year in nanoseconds, used to turn the APR into a reward rate
+pub const NANOSECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60 * 1_000_000_000;
+
+/// The default unbonding delay in epochs
+pub const DEFAULT_UNBONDING_EPOCHS: EpochHeight = 4;
+
+/// The maximum number of unbonding entries an account can have at once
+pub const MAX_UNBONDING_ENTRIES: usize = 10;
+
+/// Where staking rewards come from
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum RewardSource {
+    /// Rewards are minted, increasing the total supply
+    Mint,
+    /// Rewards are paid out of the reserve funded by the owner. Unpaid rewards stay pending until it's refilled.
+    Reserve,
+}
+
+/// The staking parameters set by the owner
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakingConfig {
+    pub apr_bps: u32, // The yearly reward rate in basis points. For example, 500 is 5%.
+    pub unbonding_epochs: EpochHeight, // How many epochs unstaked tokens are locked before they can be withdrawn
+    pub reward_source: RewardSource, // Whether rewards are minted or paid out of the reserve
+}
+
+impl Default for StakingConfig {
+    fn default() -> Self {
+        Self {
+            apr_bps: 0,
+            unbonding_epochs: DEFAULT_UNBONDING_EPOCHS,
+            reward_source: RewardSource::Reserve,
+        }
+    }
+}
+
+/// An amount of unstaked tokens waiting for its unbonding delay to pass
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UnbondingEntry {
+    pub amount: U128, // The amount of tokens being unbonded
+    pub unlock_epoch: U64, // The epoch from which the tokens can be withdrawn
+}
+
+/// The staking state of an account
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct StakeAccount {
+    /// The amount of tokens staked
+    pub staked: Balance,
+    /// The value of the reward-per-token accumulator when the rewards of this stake were last settled
+    pub reward_per_token_paid: Balance,
+    /// Rewards that were settled but not claimed yet
+    pub pending_rewards: Balance,
+    /// Unstaked tokens waiting for their unbonding delay to pass
+    pub unbonding: Vec<UnbondingEntry>,
+}
+
+impl StakeAccount {
+    /// Whether the account has nothing left in the staking pool, so that its entry can be removed
+    fn is_empty(&self) -> bool {
+        self.staked == 0 && self.pending_rewards == 0 && self.unbonding.is_empty()
+    }
+}
+
+/// The in-contract staking pool
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StakingPool {
+    /// The staking parameters set by the owner
+    pub config: StakingConfig,
+    /// Keep track of the staking state of each account
+    pub stakes: LookupMap<AccountId, StakeAccount>,
+    /// The total amount of tokens staked by all accounts
+    pub total_staked: Balance,
+    /// The rewards earned by a single staked token since the pool was created, times `REWARD_PER_TOKEN_PRECISION`
+    pub reward_per_token: Balance,
+    /// The block timestamp at which `reward_per_token` was last updated
+    pub last_reward_update: u64,
+    /// The tokens the owner set aside for paying rewards
+    pub reward_reserve: Balance,
+    /// The rewards earned by all stakes up to `last_reward_update` that weren't claimed yet. This is rounded up, so it's
+    /// never less than what the stakes settle to.
+    pub rewards_owed: Balance,
+}
+
+impl StakingPool {
+    /// Creates an empty staking pool with the default config
+    pub fn new() -> Self {
+        Self {
+            config: StakingConfig::default(),
+            stakes: LookupMap::new(StorageKey::Stakes.try_to_vec().unwrap()),
+            total_staked: 0,
+            reward_per_token: 0,
+            last_reward_update: env::block_timestamp(),
+            reward_reserve: 0,
+            rewards_owed: 0,
+        }
+    }
+
+    /// The value of the reward-per-token accumulator at the given timestamp
+    fn reward_per_token_at(&self, now: u64) -> Balance {
+        let elapsed = Balance::from(now.saturating_sub(self.last_reward_update));
+        self.reward_per_token
+            + mul_div(
+                elapsed * Balance::from(self.config.apr_bps),
+                REWARD_PER_TOKEN_PRECISION,
+                Balance::from(APR_BPS_DENOMINATOR) * Balance::from(NANOSECONDS_PER_YEAR),
+                false,
+            )
+    }
+
+    /// Brings the reward-per-token accumulator up to date. This must be called before the APR changes.
+    fn update_reward_per_token(&mut self) {
+        let now = env::block_timestamp();
+        let reward_per_token = self.reward_per_token_at(now);
+        // Every staked token earned the increase of the accumulator since the last update
+        self.rewards_owed += mul_div(
+            self.total_staked,
+            reward_per_token - self.reward_per_token,
+            REWARD_PER_TOKEN_PRECISION,
+            true,
+        );
+        self.reward_per_token = reward_per_token;
+        self.last_reward_update = now;
+    }
+
+    /// Moves the rewards a stake earned since it was last settled into its pending rewards
+    fn settle_rewards(&self, stake: &mut StakeAccount) {
+        stake.pending_rewards += mul_div(
+            stake.staked,
+            self.reward_per_token - stake.reward_per_token_paid,
+            REWARD_PER_TOKEN_PRECISION,
+            false,
+        );
+        stake.reward_per_token_paid = self.reward_per_token;
+    }
+}
+
+impl Default for StakingPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Contract {
+    /// Internal method for getting the up to date staking state of an account, with its rewards settled.
+    fn internal_get_stake(&mut self, account_id: &AccountId) -> StakeAccount {
+        self.staking.update_reward_per_token();
+        let mut stake = self.staking.stakes.get(account_id).unwrap_or_else(|| StakeAccount {
+            reward_per_token_paid: self.staking.reward_per_token,
+            ..Default::default()
+        });
+        self.staking.settle_rewards(&mut stake);
+        stake
+    }
+
+    /// Internal method for saving the staking state of an account. Empty entries are removed to free up storage.
+    fn internal_save_stake(&mut self, account_id: &AccountId, stake: &StakeAccount) {
+        if stake.is_empty() {
+            self.staking.stakes.remove(account_id);
+        } else {
+            self.staking.stakes.insert(account_id, stake);
+        }
+    }
+
+    /// Internal method for ensuring that the user attached at least 1 yoctoNEAR. This is for security and so that the
+    /// user will be required to sign with a FAK. Any extra deposit covers the storage of the call.
+    fn assert_at_least_one_yocto(&self) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Stakes some of the caller's tokens. Staked tokens can't be transferred but earn rewards at the configured APR.
+    ///
+    /// At least 1 yoctoNEAR must be attached, plus enough to cover the storage of the stake the first time.
+    /// Any excess is refunded.
+    #[payable]
+    pub fn ft_stake(&mut self, amount: U128) {
+        self.assert_at_least_one_yocto();
+        require!(self.rebasing.is_none(), "Staking isn't supported for rebasing tokens");
+        require!(amount.0 > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        // Ensure the account is on the allowlist if the contract is permissioned
+        self.assert_allowlisted(&account_id);
+        let initial_storage_usage = env::storage_usage();
+
+        // Move the tokens from the balance into the stake
+        self.internal_withdraw(&account_id, amount.0);
+        let mut stake = self.internal_get_stake(&account_id);
+        stake.staked += amount.0;
+        self.staking.total_staked += amount.0;
+        self.internal_save_stake(&account_id, &stake);
+
+        // The staked tokens are now held by the contract
+        FtTransfer {
+            old_owner_id: &account_id,
+            new_owner_id: &env::current_account_id(),
+            amount: &amount,
+            memo: Some("Stake"),
+        }
+        .emit();
+
+        // Charge the caller for the storage of the stake and refund any excess $NEAR
+        self.internal_settle_storage_change(initial_storage_usage);
+    }
+
+    /// Unstakes some of the caller's tokens. They stop earning rewards right away and can be withdrawn with
+    /// `ft_withdraw_unstaked` once the unbonding delay has passed.
+    ///
+    /// At least 1 yoctoNEAR must be attached, plus enough to cover the storage of the unbonding entry. Any excess is refunded.
+    #[payable]
+    pub fn ft_unstake(&mut self, amount: U128) {
+        self.assert_at_least_one_yocto();
+        require!(amount.0 > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+
+        let mut stake = self.internal_get_stake(&account_id);
+        require!(stake.staked >= amount.0, "The account doesn't have enough staked balance");
+        stake.staked -= amount.0;
+        self.staking.total_staked -= amount.0;
+
+        // Add the tokens to the unbonding entry of the unlock epoch, creating it if needed
+        let unlock_epoch = env::epoch_height() + self.staking.config.unbonding_epochs;
+        match stake.unbonding.iter_mut().find(|entry| entry.unlock_epoch.0 == unlock_epoch) {
+            Some(entry) => entry.amount = U128(entry.amount.0 + amount.0),
+            None => {
+                require!(
+                    stake.unbonding.len() < MAX_UNBONDING_ENTRIES,
+                    format!("An account can't have more than {} unbonding entries", MAX_UNBONDING_ENTRIES)
+                );
+                stake.unbonding.push(UnbondingEntry { amount, unlock_epoch: U64(unlock_epoch) });
+            }
+        }
+        self.internal_save_stake(&account_id, &stake);
+
+        // Charge the caller for the storage of the unbonding entry and refund any excess $NEAR
+        self.internal_settle_storage_change(initial_storage_usage);
+    }
+
+    /// Moves all of the caller's unstaked tokens whose unbonding delay has passed back to their balance.
+    /// At least 1 yoctoNEAR must be attached. The storage that was freed up is refunded.
+    ///
+    /// Returns the amount of tokens withdrawn.
+    #[payable]
+    pub fn ft_withdraw_unstaked(&mut self) -> U128 {
+        self.assert_at_least_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+
+        let mut stake = self.internal_get_stake(&account_id);
+        let current_epoch = env::epoch_height();
+        let (unlocked, unbonding): (Vec<UnbondingEntry>, Vec<UnbondingEntry>) = stake
+            .unbonding
+            .drain(..)
+            .partition(|entry| entry.unlock_epoch.0 <= current_epoch);
+        stake.unbonding = unbonding;
+        let amount: Balance = unlocked.iter().map(|entry| entry.amount.0).sum();
+        require!(amount > 0, "No unstaked tokens are ready to be withdrawn");
+        self.internal_save_stake(&account_id, &stake);
+
+        // Move the tokens back to the balance
+        self.internal_deposit(&account_id, amount);
+        FtTransfer {
+            old_owner_id: &env::current_account_id(),
+            new_owner_id: &account_id,
+            amount: &U128(amount),
+            memo: Some("Withdraw unstaked"),
+        }
+        .emit();
+
+        // Refund the storage that was freed up along with the deposit
+        self.internal_settle_storage_change(initial_storage_usage);
+        amount.into()
+    }
+
+    /// Pays the caller's pending staking rewards into their balance. If rewards are paid out of the reserve and it
+    /// doesn't hold enough, the rest stays pending until the owner refills it. At least 1 yoctoNEAR must be attached.
+    ///
+    /// Returns the amount of tokens paid.
+    #[payable]
+    pub fn ft_claim_staking_rewards(&mut self) -> U128 {
+        self.assert_at_least_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+
+        let mut stake = self.internal_get_stake(&account_id);
+        let amount = match self.staking.config.reward_source {
+            RewardSource::Mint => stake.pending_rewards,
+            RewardSource::Reserve => std::cmp::min(stake.pending_rewards, self.staking.reward_reserve),
+        };
+        require!(amount > 0, "There are no rewards to claim");
+        stake.pending_rewards -= amount;
+        self.staking.rewards_owed = self.staking.rewards_owed.saturating_sub(amount);
+        self.internal_save_stake(&account_id, &stake);
+
+        self.internal_deposit(&account_id, amount);
+        match self.staking.config.reward_source {
+            RewardSource::Mint => {
+                // Minted rewards increase the total supply
+                self.total_supply = self
+                    .total_supply
+                    .checked_add(amount)
+                    .unwrap_or_else(|| env::panic_str("Total supply overflow"));
+                FtMint {
+                    owner_id: &account_id,
+                    amount: &U128(amount),
+                    memo: Some("Staking rewards"),
+                }
+                .emit();
+            }
+            RewardSource::Reserve => {
+                // Reserve rewards were already part of the total supply
+                self.staking.reward_reserve -= amount;
+                FtTransfer {
+                    old_owner_id: &env::current_account_id(),
+                    new_owner_id: &account_id,
+                    amount: &U128(amount),
+                    memo: Some("Staking rewards"),
+                }
+                .emit();
+            }
+        }
+
+        // Refund the storage that was freed up along with the deposit
+        self.internal_settle_storage_change(initial_storage_usage);
+        amount.into()
+    }
+
+    /// Moves tokens from the owner's balance into the reserve that staking rewards are paid from. This doesn't count
+    /// towards the owner's daily transfer limit. Only the owner can call this method and exactly 1 yoctoNEAR must be attached.
+    ///
+    /// The reserve isn't adjusted when the token rebases, so it can't be funded while rebasing is enabled.
+    #[payable]
+    pub fn ft_fund_staking_rewards(&mut self, amount: U128) {
+        // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
+        assert_one_yocto();
+        self.assert_owner();
+        require!(self.rebasing.is_none(), "Staking isn't supported for rebasing tokens");
+        require!(amount.0 > 0, "The amount should be a positive number");
+
+        let owner_id = self.owner_id.clone();
+        self.internal_withdraw(&owner_id, amount.0);
+        self.staking.reward_reserve += amount.0;
+        FtTransfer {
+            old_owner_id: &owner_id,
+            new_owner_id: &env::current_account_id(),
+            amount: &amount,
+            memo: Some("Fund staking rewards"),
+        }
+        .emit();
+    }
+
+    /// Moves tokens from the reserve back to the owner's balance. Only the part of the reserve that isn't owed to stakers
+    /// for the rewards they earned so far can be taken back. If `amount` is omitted, all of it is.
+    /// Only the owner can call this method and exactly 1 yoctoNEAR must be attached.
+    ///
+    /// Returns the amount of tokens moved back.
+    #[payable]
+    pub fn ft_defund_staking_rewards(&mut self, amount: Option<U128>) -> U128 {
+        // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
+        assert_one_yocto();
+        self.assert_owner();
+
+        // Accrue the rewards earned up to now so that they stay in the reserve
+        self.staking.update_reward_per_token();
+        let available = self.staking.reward_reserve.saturating_sub(self.staking.rewards_owed);
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        require!(amount > 0, "The amount should be a positive number");
+        require!(
+            amount <= available,
+            format!("Only {} tokens of the reserve aren't owed to stakers", available)
+        );
+
+        let owner_id = self.owner_id.clone();
+        self.staking.reward_reserve -= amount;
+        self.internal_deposit(&owner_id, amount);
+        FtTransfer {
+            old_owner_id: &env::current_account_id(),
+            new_owner_id: &owner_id,
+            amount: &U128(amount),
+            memo: Some("Defund staking rewards"),
+        }
+        .emit();
+        amount.into()
+    }
+
+    /// Sets the staking parameters. Rewards earned so far are kept at the old APR. Only the owner can call this method.
+    ///
+    /// Changing the unbonding delay only affects tokens unstaked afterwards.
+    pub fn set_staking_config(&mut self, config: StakingConfig) {
+        self.assert_owner();
+        require!(
+            config.apr_bps <= MAX_APR_BPS,
+            format!("The APR can't be more than {} basis points", MAX_APR_BPS)
+        );
+
+        // Accrue the rewards earned at the old APR before switching to the new one
+        self.staking.update_reward_per_token();
+        self.staking.config = config;
+    }
+
+    /// Returns the staking parameters.
+    pub fn ft_staking_config(&self) -> StakingConfig {
+        self.staking.config.clone()
+    }
+
+    /// Returns the total amount of tokens staked by all accounts.
+    pub fn ft_total_staked(&self) -> U128 {
+        self.staking.total_staked.into()
+    }
+
+    /// Returns the tokens left in the reserve that staking rewards are paid from.
+    pub fn ft_staking_reward_reserve(&self) -> U128 {
+        self.staking.reward_reserve.into()
+    }
+
+    /// Returns the amount of tokens an account has staked.
+    pub fn ft_staked_balance_of(&self, account_id: AccountId) -> U128 {
+        self.staking
+            .stakes
+            .get(&account_id)
+            .map(|stake| stake.staked)
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Returns the rewards an account earned and hasn't claimed yet, up to the current block.
+    pub fn ft_pending_staking_rewards_of(&self, account_id: AccountId) -> U128 {
+        let reward_per_token = self.staking.reward_per_token_at(env::block_timestamp());
+        self.staking
+            .stakes
+            .get(&account_id)
+            .map(|stake| {
+                stake.pending_rewards
+                    + mul_div(
+                        stake.staked,
+                        reward_per_token - stake.reward_per_token_paid,
+                        REWARD_PER_TOKEN_PRECISION,
+                        false,
+                    )
+            })
+            .unwrap_or(0)
+            .into()
+    }
+
+    /// Returns the unstaked tokens of an account that are waiting for their unbonding delay to pass or can be withdrawn.
+    pub fn ft_unbonding_of(&self, account_id: AccountId) -> Vec<UnbondingEntry> {
+        self.staking
+            .stakes
+            .get(&account_id)
+            .map(|stake| stake.unbonding)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    use super::*;
+    use crate::ft_core::FungibleTokenCore;
+
+    const HALF_YEAR: u64 = NANOSECONDS_PER_YEAR / 2;
+
+    fn owner() -> AccountId {
+        AccountId::new_unchecked("owner.near".to_string())
+    }
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice.near".to_string())
+    }
+
+    fn metadata() -> FungibleTokenMetadata {
+        near_sdk::serde_json::from_str(r#"{"spec":"ft-1.0.0","name":"Token","symbol":"TKN","decimals":24}"#).unwrap()
+    }
+
+    // Sets up the context of a call from the given account at the given time and epoch. Calls attach enough $NEAR
+    // to cover their storage.
+    fn set_context(predecessor_id: AccountId, block_timestamp: u64, epoch_height: EpochHeight, deposit: Balance) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(AccountId::new_unchecked("ft.near".to_string()))
+            .predecessor_account_id(predecessor_id)
+            .block_timestamp(block_timestamp)
+            .epoch_height(epoch_height)
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    // Creates a token whose owner holds the supply, gives alice 1,000,000 tokens to stake and sets the APR to 10%
+    fn setup(reward_source: RewardSource) -> Contract {
+        set_context(owner(), 0, 0, 0);
+        let mut contract = Contract::new(owner(), U128(10_000_000), metadata(), None, None);
+        contract.internal_register_account(&alice(), 0);
+        contract.internal_withdraw(&owner(), 1_000_000);
+        contract.internal_deposit(&alice(), 1_000_000);
+        contract.set_staking_config(StakingConfig {
+            apr_bps: 1_000,
+            unbonding_epochs: DEFAULT_UNBONDING_EPOCHS,
+            reward_source,
+        });
+
+        set_context(alice(), 0, 0, ONE_NEAR);
+        contract.ft_stake(U128(1_000_000));
+        contract
+    }
+
+    #[test]
+    fn rewards_accrue_over_time() {
+        let contract = setup(RewardSource::Mint);
+        assert_eq!(contract.ft_balance_of(alice()), U128(0));
+        assert_eq!(contract.ft_total_staked(), U128(1_000_000));
+
+        set_context(alice(), HALF_YEAR / 2, 0, 0);
+        assert_eq!(contract.ft_pending_staking_rewards_of(alice()), U128(25_000));
+        set_context(alice(), HALF_YEAR, 0, 0);
+        assert_eq!(contract.ft_pending_staking_rewards_of(alice()), U128(50_000));
+    }
+
+    #[test]
+    fn minted_rewards_increase_the_supply() {
+        let mut contract = setup(RewardSource::Mint);
+
+        set_context(alice(), HALF_YEAR, 0, 1);
+        assert_eq!(contract.ft_claim_staking_rewards(), U128(50_000));
+        assert_eq!(contract.ft_balance_of(alice()), U128(50_000));
+        assert_eq!(contract.ft_total_supply(), U128(10_050_000));
+        assert_eq!(contract.ft_pending_staking_rewards_of(alice()), U128(0));
+    }
+
+    #[test]
+    fn reserve_rewards_stay_pending_until_refilled() {
+        let mut contract = setup(RewardSource::Reserve);
+        set_context(owner(), 0, 0, 1);
+        contract.ft_fund_staking_rewards(U128(20_000));
+
+        // The reserve only covers part of the rewards, so the rest stays pending
+        set_context(alice(), HALF_YEAR, 0, 1);
+        assert_eq!(contract.ft_claim_staking_rewards(), U128(20_000));
+        assert_eq!(contract.ft_pending_staking_rewards_of(alice()), U128(30_000));
+        assert_eq!(contract.ft_staking_reward_reserve(), U128(0));
+
+        // Once the owner refills the reserve the rest can be claimed. Reserve rewards don't change the supply.
+        set_context(owner(), HALF_YEAR, 0, 1);
+        contract.ft_fund_staking_rewards(U128(40_000));
+        set_context(alice(), HALF_YEAR, 0, 1);
+        assert_eq!(contract.ft_claim_staking_rewards(), U128(30_000));
+        assert_eq!(contract.ft_balance_of(alice()), U128(50_000));
+        assert_eq!(contract.ft_total_supply(), U128(10_000_000));
+    }
+
+    #[test]
+    fn defund_only_returns_what_is_not_owed() {
+        let mut contract = setup(RewardSource::Reserve);
+        set_context(owner(), 0, 0, 1);
+        contract.ft_fund_staking_rewards(U128(80_000));
+
+        // Half a year later 50,000 tokens are owed to alice, so only 30,000 can be taken back
+        set_context(owner(), HALF_YEAR, 0, 1);
+        assert_eq!(contract.ft_defund_staking_rewards(None), U128(30_000));
+        assert_eq!(contract.ft_staking_reward_reserve(), U128(50_000));
+        assert_eq!(contract.ft_balance_of(owner()), U128(8_950_000));
+
+        // Alice can still claim everything she earned
+        set_context(alice(), HALF_YEAR, 0, 1);
+        assert_eq!(contract.ft_claim_staking_rewards(), U128(50_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "aren't owed to stakers")]
+    fn defund_rejects_owed_rewards() {
+        let mut contract = setup(RewardSource::Reserve);
+        set_context(owner(), 0, 0, 1);
+        contract.ft_fund_staking_rewards(U128(80_000));
+
+        set_context(owner(), HALF_YEAR, 0, 1);
+        contract.ft_defund_staking_rewards(Some(U128(30_001)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Staking isn't supported for rebasing tokens")]
+    fn funding_is_rejected_while_rebasing() {
+        set_context(owner(), 0, 0, 1);
+        let mut contract = Contract::new(owner(), U128(10_000_000), metadata(), None, Some(true));
+        contract.ft_fund_staking_rewards(U128(1_000));
+    }
+
+    #[test]
+    fn unstaked_tokens_unlock_after_the_unbonding_epochs() {
+        let mut contract = setup(RewardSource::Mint);
+        set_context(alice(), 0, 10, ONE_NEAR);
+        contract.ft_unstake(U128(400_000));
+        assert_eq!(contract.ft_total_staked(), U128(600_000));
+        let unbonding = contract.ft_unbonding_of(alice());
+        assert_eq!(unbonding.len(), 1);
+        assert_eq!(unbonding[0].unlock_epoch, U64(10 + DEFAULT_UNBONDING_EPOCHS));
+
+        set_context(alice(), 0, 10 + DEFAULT_UNBONDING_EPOCHS, 1);
+        assert_eq!(contract.ft_withdraw_unstaked(), U128(400_000));
+        assert_eq!(contract.ft_balance_of(alice()), U128(400_000));
+        assert!(contract.ft_unbonding_of(alice()).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "No unstaked tokens are ready to be withdrawn")]
+    fn unstaked_tokens_are_locked_before_the_unbonding_epochs() {
+        let mut contract = setup(RewardSource::Mint);
+        set_context(alice(), 0, 10, ONE_NEAR);
+        contract.ft_unstake(U128(400_000));
+
+        set_context(alice(), 0, 10 + DEFAULT_UNBONDING_EPOCHS - 1, 1);
+        contract.ft_withdraw_unstaked();
+    }
+}
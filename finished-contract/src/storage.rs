@@ -1,5 +1,5 @@
 use near_sdk::json_types::U128;
-use near_sdk::{env, log, AccountId, Balance, Promise};
+use near_sdk::{assert_one_yocto, env, log, require, AccountId, Balance, Promise};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Deserialize, Serialize};
 
@@ -61,6 +61,22 @@ pub trait StorageManagement {
         registration_only: Option<bool>,
     ) -> StorageBalance;
 
+    // Withdraw specified amount of available Ⓝ for predecessor account.
+    //
+    // This method is safe to call. It MUST NOT remove data.
+    //
+    // `amount` is sent as a string representing an unsigned 128-bit integer. If
+    // omitted, contract MUST refund full `available` balance. If `amount` exceeds
+    // predecessor account's available balance, contract MUST panic.
+    //
+    // If predecessor account not registered, contract MUST panic.
+    //
+    // MUST require exactly 1 yoctoNEAR attached balance to prevent restricted
+    // function-call access-key call (UX wallet security)
+    //
+    // Returns the StorageBalance structure showing updated balances.
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance;
+
     /****************/
     /* VIEW METHODS */
     /****************/
@@ -77,7 +93,6 @@ pub trait StorageManagement {
 
 #[near_bindgen]
 impl StorageManagement for Contract {
-    #[allow(unused_variables)]
     #[payable]
     fn storage_deposit(
         &mut self,
@@ -88,23 +103,39 @@ impl StorageManagement for Contract {
         let amount: Balance = env::attached_deposit();
         // If an account was specified, use that. Otherwise, use the predecessor account.
         let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
-        
-        // If the account is already registered, refund the deposit.
-        if self.accounts.contains_key(&account_id) {
-            log!("The account is already registered, refunding the deposit");
-            if amount > 0 {
-                Promise::new(env::predecessor_account_id()).transfer(amount);
-            } 
+        // Get the minimum required storage
+        let min_balance = self.storage_balance_bounds().min.0;
+
+        if let Some(mut account) = self.internal_get_account(&account_id) {
+            // If the account is already registered, top its deposit up to the minimum in case the storage cost went up
+            // since it registered, and refund the rest. Registration only deposits are refunded in full.
+            let top_up = if registration_only.unwrap_or(false) {
+                0
+            } else {
+                amount.min(min_balance.saturating_sub(account.storage_paid))
+            };
+            if top_up > 0 {
+                account.storage_paid += top_up;
+                self.internal_set_account(&account_id, &account);
+            } else {
+                log!("The account is already registered, refunding the deposit");
+            }
+            let refund = amount - top_up;
+            if refund > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(refund);
+            }
         // Register the account and refund any excess $NEAR
         } else {
-            // Get the minimum required storage and ensure the deposit is at least that amount
-            let min_balance = self.storage_balance_bounds().min.0;
+            // If the contract is permissioned, only accounts on the allowlist can register
+            self.assert_allowlisted(&account_id);
+
+            // Ensure the deposit is at least the minimum required storage
             if amount < min_balance {
                 env::panic_str("The attached deposit is less than the minimum storage balance");
             }
 
-            // Register the account
-            self.internal_register_account(&account_id);
+            // Register the account and record what it paid
+            self.internal_register_account(&account_id, min_balance);
             // Perform a refund
             let refund = amount - min_balance;
             if refund > 0 {
@@ -113,13 +144,39 @@ impl StorageManagement for Contract {
         }
 
         // Return the storage balance of the account
-        StorageBalance { total: self.storage_balance_bounds().min, available: 0.into() }
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self.internal_unwrap_account(&account_id);
+
+        // Free up the history records that no longer fit in the history size, so that their storage can be withdrawn
+        self.internal_trim_transfer_history(&account_id);
+
+        // Only the part of the deposit above what the account's storage currently costs can be withdrawn
+        let available = self.internal_storage_balance(&account_id, &account).available.0;
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        require!(amount <= available, "The amount is greater than the available storage balance");
+
+        if amount > 0 {
+            account.storage_paid -= amount;
+            self.internal_set_account(&account_id, &account);
+            Promise::new(account_id.clone()).transfer(amount);
+        }
+
+        // Return the updated storage balance of the account
+        self.internal_storage_balance(&account_id, &account)
     }
 
     fn storage_balance_bounds(&self) -> StorageBalanceBounds {
-        // Calculate the required storage balance by taking the bytes for the longest account ID and multiplying by the current byte cost
+        // Calculate the required storage balance by taking the bytes for the longest account ID plus the bytes for a full
+        // transfer history and multiplying by the current byte cost
         let required_storage_balance =
-            Balance::from(self.bytes_for_longest_account_id) * env::storage_byte_cost();
+            Balance::from(self.bytes_for_longest_account_id + self.bytes_for_full_transfer_history()) * env::storage_byte_cost();
         
         // Storage balance bounds will have min == max == required_storage_balance
         StorageBalanceBounds {
@@ -129,11 +186,59 @@ impl StorageManagement for Contract {
     }
 
     fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
-        // Get the storage balance of the account. Available will always be 0 since you can't overpay for storage.
-        if self.accounts.contains_key(&account_id) {
-            Some(StorageBalance { total: self.storage_balance_bounds().min, available: 0.into() })
-        } else {
-            None
+        // Get the storage balance of the account from what it actually paid
+        self.internal_get_account(&account_id)
+            .map(|account| self.internal_storage_balance(&account_id, &account))
+    }
+}
+
+impl Contract {
+    /// Internal method for getting the storage balance of an account. The total is what the account paid for its
+    /// storage. Anything above the current minimum storage balance is available to withdraw, as long as it's not
+    /// still paying for history records that were kept from a larger history size.
+    pub(crate) fn internal_storage_balance(&self, account_id: &AccountId, account: &AccountRecord) -> StorageBalance {
+        let min_balance = self.storage_balance_bounds().min.0;
+        let stored_bytes = self.bytes_for_longest_account_id + self.internal_transfer_history_bytes(account_id);
+        let locked = std::cmp::max(min_balance, Balance::from(stored_bytes) * env::storage_byte_cost());
+        StorageBalance {
+            total: account.storage_paid.into(),
+            available: account.storage_paid.saturating_sub(locked).into(),
         }
     }
 }
+
+#[near_bindgen]
+impl Contract {
+    /// Re-measures the bytes each account needs after the storage layout changed, for example after a migration.
+    /// The new cost applies to `storage_balance_bounds` right away: accounts that paid more than the new minimum can
+    /// withdraw the excess with `storage_withdraw`. Only the owner can call this method.
+    ///
+    /// Returns the new storage balance bounds.
+    pub fn remeasure_storage_cost(&mut self) -> StorageBalanceBounds {
+        self.assert_owner();
+
+        // Measure the bytes for the longest account ID and the transfer history again
+        self.measure_bytes_for_longest_account_id();
+        self.measure_bytes_for_transfer_history();
+        log!(
+            "Measured {} bytes per account and {} bytes for a full transfer history",
+            self.bytes_for_longest_account_id,
+            self.bytes_for_full_transfer_history()
+        );
+
+        self.storage_balance_bounds()
+    }
+
+    /// Returns the storage balances of several accounts at once, in the same order as `account_ids`. Accounts that
+    /// aren't registered return `null`. At most `MAX_BATCH_VIEW_ACCOUNTS` accounts can be queried at once.
+    pub fn storage_balances_of(&self, account_ids: Vec<AccountId>) -> Vec<Option<StorageBalance>> {
+        require!(
+            account_ids.len() <= MAX_BATCH_VIEW_ACCOUNTS,
+            format!("Can't query more than {} accounts at once", MAX_BATCH_VIEW_ACCOUNTS)
+        );
+        account_ids
+            .into_iter()
+            .map(|account_id| self.storage_balance_of(account_id))
+            .collect()
+    }
+}
@@ -0,0 +1,194 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Gas, Promise, PromiseOrValue};
+
+use crate::ft_core::ext_ft_receiver;
+use crate::*;
+
+/// The most receivers a single `ft_transfer_call_many` can ever have. The actual limit is usually lower since every
+/// receiver needs the gas of the gas settings (see `ft_max_transfer_call_receivers`).
+pub const MAX_TRANSFER_CALL_RECEIVERS: usize = 10;
+
+/// The gas kept for running `ft_transfer_call_many` itself, which moves the tokens, emits the events and creates the calls
+pub const GAS_FOR_TRANSFER_CALL_MANY: Gas = Gas(50_000_000_000_000);
+
+/// One of the receivers of an `ft_transfer_call_many`
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferCallReceiver {
+    pub receiver_id: AccountId, // The receiver contract. Its `ft_on_transfer` method will be called.
+    pub amount: U128, // The part of the total amount sent to this receiver
+    pub msg: String, // The message passed to this receiver's `ft_on_transfer` call
+    pub gas_weight: Option<u64>, // This receiver's share of the unused gas relative to the other receivers. Defaults to 1.
+}
+
+impl Contract {
+    /// Internal method for transferring FTs from one account to several others. The sender is debited once for the
+    /// whole batch, and every receiver is credited the shares of its part so that a rebasing contract moves the same
+    /// number of shares out of the sender as into the receivers.
+    pub(crate) fn internal_transfer_many(
+        &mut self,
+        sender_id: &AccountId,
+        transfers: &[(AccountId, Balance)],
+        memo: Option<String>,
+    ) {
+        // Ensure the sender is on the allowlist if the contract is permissioned
+        self.assert_allowlisted(sender_id);
+
+        let mut total: Balance = 0;
+        let mut total_shares: Balance = 0;
+        for (index, (receiver_id, amount)) in transfers.iter().enumerate() {
+            // Ensure the sender can't transfer to themselves or to the same receiver twice
+            require!(sender_id != receiver_id, "Sender and receiver should be different");
+            require!(
+                transfers[..index].iter().all(|(other_id, _)| other_id != receiver_id),
+                "Receivers should be different"
+            );
+            // Ensure the sender can't transfer 0 tokens
+            require!(*amount > 0, "The amount should be a positive number");
+            // Ensure the receiver is on the allowlist if the contract is permissioned
+            self.assert_allowlisted(receiver_id);
+
+            total = total
+                .checked_add(*amount)
+                .unwrap_or_else(|| env::panic_str("Total amount overflow"));
+            total_shares = total_shares
+                .checked_add(self.internal_amount_to_shares(*amount))
+                .unwrap_or_else(|| env::panic_str("Total amount overflow"));
+        }
+
+        // Spend the whole batch from the sender's daily allowance and withdraw it at once
+        self.internal_spend_daily_allowance(sender_id, total);
+        self.internal_withdraw_shares(sender_id, total_shares);
+
+        // Deposit each part into its receiver
+        for (receiver_id, amount) in transfers {
+            self.internal_deposit_shares(receiver_id, self.internal_amount_to_shares(*amount));
+            // Record the transfer in the history of both accounts
+            self.internal_record_transfer(sender_id, receiver_id, *amount, memo.as_deref());
+        }
+
+        // Emit a Transfer event for every receiver
+        let amounts: Vec<U128> = transfers.iter().map(|(_, amount)| U128(*amount)).collect();
+        let events: Vec<FtTransfer> = transfers
+            .iter()
+            .zip(amounts.iter())
+            .map(|((receiver_id, _), amount)| FtTransfer {
+                old_owner_id: sender_id,
+                new_owner_id: receiver_id,
+                amount,
+                memo: memo.as_deref(),
+            })
+            .collect();
+        FtTransfer::emit_many(&events);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers tokens from the `env::predecessor_account_id` to several receiver contracts at once, then calls
+    /// `ft_on_transfer` on each of them with its own `msg`. All the calls are joined and resolved together in
+    /// `ft_resolve_transfer_many`, which refunds every receiver's unused amount with the same rules as `ft_resolve_transfer`.
+    ///
+    /// Gas is split as follows:
+    /// - Every receiver gets the `ft_on_transfer` gas of the gas settings (see `ft_gas_settings`) and the resolve
+    ///   callback gets their `resolve_transfer` gas once per receiver. The attached gas must cover all of it.
+    /// - All remaining gas is split between the receivers according to their `gas_weight` (1 by default). For
+    ///   example, weights of 2 and 1 give the first receiver twice as much of the remaining gas as the second.
+    ///
+    /// All receivers must be registered and exactly 1 yoctoNEAR must be attached. There can be at most
+    /// `ft_max_transfer_call_receivers` receivers, which is 8 with the default gas settings.
+    ///
+    /// Returns a promise which will result in the amount of tokens used by each receiver, in the order they were given.
+    #[payable]
+    pub fn ft_transfer_call_many(
+        &mut self,
+        receivers: Vec<TransferCallReceiver>,
+        memo: Option<String>,
+    ) -> PromiseOrValue<Vec<U128>> {
+        // Assert that the user attached exactly 1 yoctoNEAR. This is for security and so that the user will be required to sign with a FAK.
+        assert_one_yocto();
+        require!(!receivers.is_empty(), "At least one receiver is required");
+        let max_receivers = self.ft_max_transfer_call_receivers();
+        require!(
+            receivers.len() <= max_receivers as usize,
+            format!("Can't transfer to more than {} receivers at once", max_receivers)
+        );
+
+        // Ensure the memo and every msg are valid before changing any state
+        self.assert_valid_memo(memo.as_deref());
+        receivers.iter().for_each(|receiver| self.assert_valid_msg(&receiver.msg));
+
+        // Ensure enough gas was attached for the static gas of every call
+        let receiver_count = receivers.len() as u64;
+        self.assert_enough_gas_for_transfer_calls(receiver_count);
+        let gas_for_resolve = Gas(self.gas_settings.resolve_transfer.0 * receiver_count);
+
+        // The sender is the user who called the method
+        let sender_id = env::predecessor_account_id();
+        // Transfer the tokens to every receiver
+        let transfers: Vec<(AccountId, Balance)> = receivers
+            .iter()
+            .map(|receiver| (receiver.receiver_id.clone(), receiver.amount.0))
+            .collect();
+        self.internal_transfer_many(&sender_id, &transfers, memo);
+
+        // Initiate every receiver's call and join them so that they're resolved together
+        let mut calls: Option<Promise> = None;
+        for receiver in receivers {
+            let call = ext_ft_receiver::ext(receiver.receiver_id)
+                .with_static_gas(self.gas_settings.ft_on_transfer)
+                .with_unused_gas_weight(receiver.gas_weight.unwrap_or(1))
+                .ft_on_transfer(sender_id.clone(), receiver.amount, receiver.msg);
+            calls = Some(match calls {
+                Some(calls) => calls.and(call),
+                None => call,
+            });
+        }
+
+        // We then resolve all the promises and call ft_resolve_transfer_many on our own contract
+        // The resolve callback doesn't get any of the unused gas so that it all goes to the receivers
+        let receivers: Vec<(AccountId, U128)> = transfers
+            .into_iter()
+            .map(|(receiver_id, amount)| (receiver_id, U128(amount)))
+            .collect();
+        calls
+            .unwrap()
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(gas_for_resolve)
+                    .with_unused_gas_weight(0)
+                    .ft_resolve_transfer_many(sender_id, receivers),
+            )
+            .into()
+    }
+
+    /// Returns the most receivers a single `ft_transfer_call_many` can have. This is how many receiver calls and
+    /// resolve callbacks fit in the prepaid gas of a transaction with the current gas settings, up to `MAX_TRANSFER_CALL_RECEIVERS`.
+    pub fn ft_max_transfer_call_receivers(&self) -> u32 {
+        let gas_per_receiver = self.gas_settings.ft_on_transfer.0 + self.gas_settings.resolve_transfer.0;
+        let receivers = (MAX_PREPAID_GAS.0 - GAS_FOR_TRANSFER_CALL_MANY.0) / gas_per_receiver;
+        std::cmp::min(receivers, MAX_TRANSFER_CALL_RECEIVERS as u64) as u32
+    }
+
+    /// Finalizes an `ft_transfer_call_many`. The result of each receiver's `ft_on_transfer` call is resolved like
+    /// in `ft_resolve_transfer`, and the sender is refunded for every unused amount.
+    ///
+    /// Returns the amount of tokens used by each receiver, in the order they were given.
+    #[private]
+    pub fn ft_resolve_transfer_many(
+        &mut self,
+        sender_id: AccountId,
+        receivers: Vec<(AccountId, U128)>,
+    ) -> Vec<U128> {
+        // The promise results are in the same order as the receivers
+        receivers
+            .iter()
+            .enumerate()
+            .map(|(index, (receiver_id, amount))| {
+                self.internal_resolve_transfer(&sender_id, receiver_id, amount.0, index as u64)
+                    .into()
+            })
+            .collect()
+    }
+}
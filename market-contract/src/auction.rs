@@ -0,0 +1,297 @@
+use crate::*;
+use near_sdk::require;
+
+//a bid in the final 10 minutes of an auction pushes the end time back
+pub const AUCTION_EXTENSION_WINDOW: u64 = 10 * 60 * 1_000_000_000;
+
+//how far the end time is pushed back from the time of a late bid (10 minutes)
+pub const AUCTION_EXTENSION: u64 = 10 * 60 * 1_000_000_000;
+
+//the auction settings passed in the msg of `nft_approve` as part of the SaleArgs
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AuctionArgs {
+    //when bidding opens in nanoseconds since the unix epoch. If omitted, bidding opens right away
+    pub start_time: Option<U64>,
+    //when bidding closes in nanoseconds since the unix epoch
+    pub end_time: U64,
+    //the lowest top bid that the token will be sold for when the auction is settled
+    pub reserve_price: U128,
+    //how much higher every bid has to be than the current top bid
+    pub min_increment: U128,
+}
+
+//the highest bid on an auction. The amount is locked from the bidder's FT balance until they're outbid or the auction is settled
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bid {
+    pub bidder_id: AccountId,
+    pub amount: U128,
+}
+
+//struct that holds the state of an english auction. The fixed price of the sale is the lowest first bid
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Auction {
+    //when bidding opens in nanoseconds since the unix epoch
+    pub start_time: U64,
+    //when bidding closes in nanoseconds since the unix epoch. Late bids push this back
+    pub end_time: U64,
+    //the lowest top bid that the token will be sold for when the auction is settled
+    pub reserve_price: U128,
+    //how much higher every bid has to be than the current top bid
+    pub min_increment: U128,
+    //the current top bid, if any
+    pub top_bid: Option<Bid>,
+}
+
+impl Auction {
+    //creates the auction for a sale from the args passed in by the seller
+    pub(crate) fn new(args: AuctionArgs) -> Self {
+        let now = env::block_timestamp();
+        let start_time = args.start_time.map(|t| t.0).unwrap_or(now);
+        //make sure the auction can actually be bid on
+        require!(args.end_time.0 > start_time, "The auction must end after it starts");
+        require!(args.end_time.0 > now, "The auction must end in the future");
+        require!(args.min_increment.0 > 0, "The minimum increment must be positive");
+
+        Self {
+            start_time: U64(start_time),
+            end_time: args.end_time,
+            reserve_price: args.reserve_price,
+            min_increment: args.min_increment,
+            top_bid: None,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Place a bid on an auction. The amount is locked from your FT balance for the FT the sale is priced in, and
+    /// the previous top bidder gets their bid back in their FT balance. A bid in the final minutes extends the auction.
+    #[payable]
+    pub fn place_bid(&mut self, nft_contract_id: AccountId, token_id: String, amount: U128) {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+
+        //get the unique sale ID (contract + DELIMITER + token ID)
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+
+        //get the sale object from the unique sale ID. If the sale doesn't exist or isn't an auction, panic.
+        let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let ft_token_id = match &sale.currency {
+            Currency::Ft(ft_token_id) => ft_token_id.clone(),
+            Currency::Near => env::panic_str("Auctions must be priced in FTs"),
+        };
+        let auction = sale.auction.as_mut().unwrap_or_else(|| env::panic_str("The sale is not an auction"));
+
+        //get the bidder ID which is the person who called the function and make sure they're not the owner of the sale
+        let bidder_id = env::predecessor_account_id();
+        assert_ne!(sale.owner_id, bidder_id, "Cannot bid on your own sale.");
+
+        //make sure the auction is open
+        let now = env::block_timestamp();
+        require!(now >= auction.start_time.0, "The auction hasn't started yet");
+        require!(now < auction.end_time.0, "The auction has ended");
+
+        //the first bid must be at least the sale price and every other bid must beat the top bid by the minimum increment
+        let min_bid = match &auction.top_bid {
+            Some(top_bid) => top_bid.amount.0 + auction.min_increment.0,
+            None => sale.sale_conditions.price_at(now),
+        };
+        assert!(amount.0 >= min_bid, "Bid must be greater than or equal to {:?}", min_bid);
+
+        // get the amount of FTs the bidder has in their balance and lock the bid from it
+        let cur_bal = self.internal_ft_deposit_of(&bidder_id, &ft_token_id);
+        assert!(cur_bal >= amount.0, "Not enough FTs in balance to cover bid: {:?}", amount);
+        self.internal_set_ft_deposit(&bidder_id, &ft_token_id, cur_bal - amount.0);
+
+        //replace the top bid and give the previous top bidder their bid back
+        let previous_bid = auction.top_bid.replace(Bid { bidder_id, amount });
+
+        //a bid in the final minutes pushes the end time back so that others have time to respond
+        if auction.end_time.0 - now < AUCTION_EXTENSION_WINDOW {
+            auction.end_time = U64(now + AUCTION_EXTENSION);
+        }
+
+        //insert the sale back into the map for the unique sale ID
+        self.sales.insert(&contract_and_token_id, &sale);
+
+        if let Some(previous_bid) = previous_bid {
+            let cur_bal = self.internal_ft_deposit_of(&previous_bid.bidder_id, &ft_token_id);
+            self.internal_set_ft_deposit(&previous_bid.bidder_id, &ft_token_id, cur_bal + previous_bid.amount.0);
+        }
+    }
+
+    /// Settle an auction that has ended. Anyone can call this. If the top bid meets the reserve price, the token is
+    /// sold to the top bidder. Otherwise the sale is removed, the top bidder gets their bid back and the token stays with the owner.
+    pub fn settle_auction(&mut self, nft_contract_id: AccountId, token_id: String) {
+        //get the unique sale ID (contract + DELIMITER + token ID)
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+
+        //get the sale object from the unique sale ID. If the sale doesn't exist or isn't an auction, panic.
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        let auction = sale.auction.unwrap_or_else(|| env::panic_str("The sale is not an auction"));
+        require!(env::block_timestamp() >= auction.end_time.0, "The auction hasn't ended yet");
+
+        match auction.top_bid {
+            //the reserve was met so the token is sold to the top bidder. Their bid was already locked when they placed it.
+            Some(top_bid) if top_bid.amount.0 >= auction.reserve_price.0 => {
+                self.process_purchase(
+                    nft_contract_id,
+                    token_id,
+                    top_bid.amount,
+                    top_bid.bidder_id,
+                );
+            }
+            //the reserve wasn't met (or nobody bid) so the sale is removed and the top bidder gets their bid back
+            top_bid => {
+                self.internal_remove_sale(nft_contract_id, token_id);
+                if let (Some(top_bid), Currency::Ft(ft_token_id)) = (top_bid, &sale.currency) {
+                    let cur_bal = self.internal_ft_deposit_of(&top_bid.bidder_id, ft_token_id);
+                    self.internal_set_ft_deposit(&top_bid.bidder_id, ft_token_id, cur_bal + top_bid.amount.0);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::nft_callbacks::NonFungibleTokenApprovalsReceiver;
+
+    const MINUTE: u64 = 60 * 1_000_000_000;
+    const END_TIME: u64 = 1_000 * MINUTE;
+
+    fn account(name: &str) -> AccountId {
+        AccountId::new_unchecked(name.to_string())
+    }
+
+    fn ft() -> AccountId {
+        account("ft.near")
+    }
+
+    fn nft() -> AccountId {
+        account("nft.near")
+    }
+
+    //sets up the context of a call from the given account at the given time with 1 yoctoNEAR attached
+    fn set_context(predecessor_id: AccountId, block_timestamp: u64) {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(account("market.near"))
+            .predecessor_account_id(predecessor_id)
+            .block_timestamp(block_timestamp)
+            .attached_deposit(1)
+            .build());
+    }
+
+    //creates a market where the seller listed token "1" in an auction starting at 100 FTs with an increment of 10
+    //and a reserve price of 150. Alice and bob each deposited 1,000 FTs
+    fn setup() -> Contract {
+        set_context(account("owner.near"), 0);
+        let mut contract = Contract::new(account("owner.near"), ft());
+        contract.storage_deposits.insert(&account("seller.near"), &STORAGE_PER_SALE);
+        contract.internal_set_ft_deposit(&account("alice.near"), &ft(), 1_000);
+        contract.internal_set_ft_deposit(&account("bob.near"), &ft(), 1_000);
+
+        //the NFT contract calls nft_on_approve when the seller approves the market
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(account("market.near"))
+            .predecessor_account_id(nft())
+            .signer_account_id(account("seller.near"))
+            .build());
+        let msg = format!(
+            r#"{{"sale_conditions":"100","auction":{{"end_time":"{}","reserve_price":"150","min_increment":"10"}}}}"#,
+            END_TIME
+        );
+        contract.nft_on_approve("1".to_string(), account("seller.near"), 0, msg);
+        contract
+    }
+
+    fn auction_of(contract: &Contract) -> Auction {
+        contract.sales.get(&format!("{}{}{}", nft(), DELIMETER, "1")).unwrap().auction.unwrap()
+    }
+
+    #[test]
+    fn outbid_bidder_gets_their_bid_back() {
+        let mut contract = setup();
+        set_context(account("alice.near"), MINUTE);
+        contract.place_bid(nft(), "1".to_string(), U128(100));
+        assert_eq!(contract.internal_ft_deposit_of(&account("alice.near"), &ft()), 900);
+
+        set_context(account("bob.near"), 2 * MINUTE);
+        contract.place_bid(nft(), "1".to_string(), U128(110));
+        assert_eq!(contract.internal_ft_deposit_of(&account("alice.near"), &ft()), 1_000);
+        assert_eq!(contract.internal_ft_deposit_of(&account("bob.near"), &ft()), 890);
+
+        let top_bid = auction_of(&contract).top_bid.unwrap();
+        assert_eq!(top_bid.bidder_id, account("bob.near"));
+        assert_eq!(top_bid.amount, U128(110));
+        //the price of an auction with bids is its top bid
+        assert_eq!(contract.get_sale_price(format!("{}{}{}", nft(), DELIMETER, "1")), Some(U128(110)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bid must be greater than or equal to 110")]
+    fn bids_must_beat_the_top_bid_by_the_increment() {
+        let mut contract = setup();
+        set_context(account("alice.near"), MINUTE);
+        contract.place_bid(nft(), "1".to_string(), U128(100));
+        set_context(account("bob.near"), 2 * MINUTE);
+        contract.place_bid(nft(), "1".to_string(), U128(109));
+    }
+
+    #[test]
+    fn late_bids_extend_the_auction() {
+        let mut contract = setup();
+
+        //a bid before the extension window doesn't move the end time
+        set_context(account("alice.near"), END_TIME - AUCTION_EXTENSION_WINDOW);
+        contract.place_bid(nft(), "1".to_string(), U128(100));
+        assert_eq!(auction_of(&contract).end_time, U64(END_TIME));
+
+        //a bid in the final minutes pushes the end time back from the time of the bid
+        let late = END_TIME - MINUTE;
+        set_context(account("bob.near"), late);
+        contract.place_bid(nft(), "1".to_string(), U128(110));
+        assert_eq!(auction_of(&contract).end_time, U64(late + AUCTION_EXTENSION));
+
+        //so the auction can't be settled at the original end time
+        set_context(account("alice.near"), END_TIME);
+        contract.place_bid(nft(), "1".to_string(), U128(120));
+        assert_eq!(auction_of(&contract).top_bid.unwrap().bidder_id, account("alice.near"));
+    }
+
+    #[test]
+    fn unmet_reserve_refunds_the_top_bidder() {
+        let mut contract = setup();
+        set_context(account("alice.near"), MINUTE);
+        contract.place_bid(nft(), "1".to_string(), U128(100));
+
+        set_context(account("anyone.near"), END_TIME);
+        contract.settle_auction(nft(), "1".to_string());
+        assert_eq!(contract.internal_ft_deposit_of(&account("alice.near"), &ft()), 1_000);
+        assert!(contract.get_sale(format!("{}{}{}", nft(), DELIMETER, "1")).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot relist an auction that has bids")]
+    fn auctions_with_bids_cannot_be_relisted() {
+        let mut contract = setup();
+        set_context(account("alice.near"), MINUTE);
+        contract.place_bid(nft(), "1".to_string(), U128(100));
+        contract.storage_deposits.insert(&account("seller.near"), &(2 * STORAGE_PER_SALE));
+
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(account("market.near"))
+            .predecessor_account_id(nft())
+            .signer_account_id(account("seller.near"))
+            .block_timestamp(2 * MINUTE)
+            .build());
+        contract.nft_on_approve("1".to_string(), account("seller.near"), 1, r#"{"sale_conditions":"1"}"#.to_string());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::*;
+use near_sdk::require;
+use std::ops::Bound;
+
+//the most token IDs a collection offer can be limited to (this is limited by the storage paid per offer)
+pub const MAX_COLLECTION_OFFER_TOKEN_IDS: usize = 5;
+
+//the longest prefix or token ID a collection offer's filter can hold in bytes (this is limited by the storage paid per offer)
+pub const MAX_TOKEN_FILTER_ID_LEN: usize = 64;
+
+//limits which tokens of a collection an offer can be filled with, for example to bid on tokens with a certain trait
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum TokenFilter {
+    //only tokens whose ID starts with the prefix
+    Prefix(String),
+    //only the listed tokens
+    TokenIds(Vec<TokenId>),
+}
+
+impl TokenFilter {
+    //makes sure the filter fits in the storage paid for the offer
+    pub(crate) fn assert_valid(&self) {
+        match self {
+            TokenFilter::Prefix(prefix) => require!(
+                prefix.len() <= MAX_TOKEN_FILTER_ID_LEN,
+                format!("The prefix can't be longer than {} bytes", MAX_TOKEN_FILTER_ID_LEN)
+            ),
+            TokenFilter::TokenIds(token_ids) => {
+                require!(
+                    !token_ids.is_empty() && token_ids.len() <= MAX_COLLECTION_OFFER_TOKEN_IDS,
+                    format!("The filter must list between 1 and {} token IDs", MAX_COLLECTION_OFFER_TOKEN_IDS)
+                );
+                require!(
+                    token_ids.iter().all(|token_id| token_id.len() <= MAX_TOKEN_FILTER_ID_LEN),
+                    format!("The token IDs can't be longer than {} bytes", MAX_TOKEN_FILTER_ID_LEN)
+                );
+            }
+        }
+    }
+
+    //returns whether a token passes the filter
+    pub(crate) fn matches(&self, token_id: &TokenId) -> bool {
+        match self {
+            TokenFilter::Prefix(prefix) => token_id.starts_with(prefix.as_str()),
+            TokenFilter::TokenIds(token_ids) => token_ids.contains(token_id),
+        }
+    }
+}
+
+//struct that holds an offer on any token of an nft contract. The price of every remaining token is locked from the
+//bidder's FT balance until the offer is filled or cancelled
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionOffer {
+    //the unique ID of the offer
+    pub offer_id: U64,
+    //the account that made the offer
+    pub bidder_id: AccountId,
+    //nft contract whose tokens the offer is for
+    pub nft_contract_id: AccountId,
+    //the FT contract the offer is paid in
+    pub ft_token_id: FungibleTokenId,
+    //how many FTs are offered for every token
+    pub price: U128,
+    //how many more tokens the offer can be filled with
+    pub quantity: u32,
+    //when the offer can no longer be filled in nanoseconds since the unix epoch
+    pub expires_at: U64,
+    //which tokens the offer can be filled with. If there's no filter, any token of the contract can be used
+    pub token_filter: Option<TokenFilter>,
+}
+
+impl CollectionOffer {
+    //returns the key of the offer in the price index. Higher prices get lower keys so that they come first
+    pub(crate) fn price_key(&self) -> (AccountId, FungibleTokenId, u128, u64) {
+        (self.nft_contract_id.clone(), self.ft_token_id.clone(), u128::MAX - self.price.0, self.offer_id.0)
+    }
+}
+
+//the args passed in the msg of `nft_approve` to fill a collection offer with the approved token
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AcceptCollectionOfferArgs {
+    //the ID of the collection offer to fill
+    pub offer_id: U64,
+    //the lowest price the owner accepts
+    pub price: U128,
+}
+
+impl Contract {
+    //internal method for getting how many collection offers an account has made
+    pub(crate) fn internal_collection_offers_of(&self, account_id: &AccountId) -> u64 {
+        self.collection_offers_by_bidder
+            .get(account_id)
+            .map(|s| s.len())
+            .unwrap_or_default()
+    }
+
+    //internal method for adding a collection offer to the market
+    pub(crate) fn internal_add_collection_offer(&mut self, offer: &CollectionOffer) {
+        self.collection_offers.insert(&offer.offer_id.0, offer);
+
+        //index the offer by its price so that the best offers on the nft contract can be found
+        self.collection_offers_by_price.insert(&offer.price_key(), &());
+
+        //get the collection offers made by the bidder. If there are none, we create a new empty set
+        let mut by_bidder = self
+            .collection_offers_by_bidder
+            .get(&offer.bidder_id)
+            .unwrap_or_else(|| {
+                UnorderedSet::new(
+                    StorageKey::CollectionOffersByBidderInner {
+                        //we get a new unique prefix for the collection by hashing the bidder
+                        account_id_hash: hash_account_id(&offer.bidder_id),
+                    }
+                    .try_to_vec()
+                    .unwrap(),
+                )
+            });
+        by_bidder.insert(&offer.offer_id.0);
+        self.collection_offers_by_bidder.insert(&offer.bidder_id, &by_bidder);
+    }
+
+    //internal method for removing a collection offer from the market. This returns the removed offer object
+    pub(crate) fn internal_remove_collection_offer(&mut self, offer_id: u64) -> CollectionOffer {
+        //get the offer object by removing it. If there was no offer, panic
+        let offer = self.collection_offers.remove(&offer_id).expect("No collection offer");
+
+        //remove the offer from the price index
+        self.collection_offers_by_price.remove(&offer.price_key());
+
+        //remove the offer from the offers made by the bidder, removing the set if it's now empty
+        let mut by_bidder = self
+            .collection_offers_by_bidder
+            .get(&offer.bidder_id)
+            .expect("No collection offer by bidder");
+        by_bidder.remove(&offer_id);
+        if by_bidder.is_empty() {
+            self.collection_offers_by_bidder.remove(&offer.bidder_id);
+        } else {
+            self.collection_offers_by_bidder.insert(&offer.bidder_id, &by_bidder);
+        }
+
+        offer
+    }
+
+    //internal method for filling a collection offer with a token on behalf of its owner. The offer must not have
+    //expired, must accept the token and must pay at least the price the owner expects. The token is transferred using
+    //the given approval ID.
+    pub(crate) fn internal_accept_collection_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        approval_id: u64,
+        owner_id: AccountId,
+        args: AcceptCollectionOfferArgs,
+    ) -> Promise {
+        let mut offer = self.collection_offers.get(&args.offer_id.0).expect("No collection offer");
+        require!(offer.nft_contract_id == nft_contract_id, "The collection offer is for another nft contract");
+        require!(env::block_timestamp() < offer.expires_at.0, "The collection offer has expired");
+        require!(
+            offer.token_filter.as_ref().is_none_or(|filter| filter.matches(&token_id)),
+            "The token doesn't match the collection offer's filter"
+        );
+        require!(offer.price.0 >= args.price.0, "The collection offer is lower than the expected price");
+        assert_ne!(owner_id, offer.bidder_id, "Cannot accept your own offer.");
+
+        //use up one token of the offer, removing it once the quantity runs out
+        offer.quantity -= 1;
+        if offer.quantity == 0 {
+            self.internal_remove_collection_offer(args.offer_id.0);
+        } else {
+            self.collection_offers.insert(&args.offer_id.0, &offer);
+        }
+
+        //the price was locked from the bidder's balance when the offer was made, so the token can be bought right away
+        self.internal_process_purchase(
+            nft_contract_id,
+            token_id,
+            approval_id,
+            owner_id,
+            Currency::Ft(offer.ft_token_id),
+            offer.price,
+            offer.bidder_id,
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Make an offer to buy `quantity` tokens of an nft contract for `price` each, optionally limited to the tokens
+    /// that pass `token_filter`. The price of every token is locked from your FT balance until the offer is filled
+    /// or cancelled. Every collection offer takes up as much storage as a sale, which must be covered by your storage deposit.
+    ///
+    /// Returns the ID of the offer.
+    #[payable]
+    pub fn make_collection_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        price: U128,
+        quantity: u32,
+        ft_token_id: Option<FungibleTokenId>,
+        expires_at: U64,
+        token_filter: Option<TokenFilter>,
+    ) -> U64 {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+
+        //make sure the offer is paid in an approved FT and can be filled
+        let ft_token_id = ft_token_id.unwrap_or_else(|| self.ft_id.clone());
+        self.assert_approved_ft_token_id(&ft_token_id);
+        require!(price.0 > 0, "The price must be positive");
+        require!(quantity > 0, "The quantity must be positive");
+        require!(expires_at.0 > env::block_timestamp(), "The offer must expire in the future");
+        if let Some(token_filter) = &token_filter {
+            token_filter.assert_valid();
+        }
+
+        //the bidder is the person who called the function
+        let bidder_id = env::predecessor_account_id();
+
+        // get the amount of FTs the bidder has in their balance and lock the price of every token from it
+        let total = price.0.checked_mul(u128::from(quantity)).expect("Offer overflow");
+        let cur_bal = self.internal_ft_deposit_of(&bidder_id, &ft_token_id);
+        assert!(cur_bal >= total, "Not enough FTs in balance to cover offer: {:?}", total);
+        self.internal_set_ft_deposit(&bidder_id, &ft_token_id, cur_bal - total);
+
+        //add the offer with the next unique ID
+        let offer_id = U64(self.next_collection_offer_id);
+        self.next_collection_offer_id += 1;
+        self.internal_add_collection_offer(&CollectionOffer {
+            offer_id,
+            bidder_id: bidder_id.clone(),
+            nft_contract_id,
+            ft_token_id,
+            price,
+            quantity,
+            expires_at,
+            token_filter,
+        });
+
+        //make sure the bidder has paid enough storage for all their sales and offers
+        let storage_paid = self.storage_deposits.get(&bidder_id).unwrap_or(0);
+        let storage_required = u128::from(self.internal_storage_items_of(&bidder_id)) * STORAGE_PER_SALE;
+        assert!(
+            storage_paid >= storage_required,
+            "Insufficient storage paid: {}, for {} sales and offers at {} rate of per sale",
+            storage_paid, storage_required / STORAGE_PER_SALE, STORAGE_PER_SALE
+        );
+
+        offer_id
+    }
+
+    /// Cancel a collection offer and get the price of the remaining tokens back in the bidder's FT balance.
+    /// Only the bidder can cancel an offer, except for offers that have expired which anyone can cancel.
+    #[payable]
+    pub fn cancel_collection_offer(&mut self, offer_id: U64) {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+
+        let offer = self.internal_remove_collection_offer(offer_id.0);
+        require!(
+            env::predecessor_account_id() == offer.bidder_id || env::block_timestamp() >= offer.expires_at.0,
+            "Only the bidder can cancel an offer that hasn't expired"
+        );
+
+        //give the price of the remaining tokens back to the bidder
+        let refund = offer.price.0 * u128::from(offer.quantity);
+        let cur_bal = self.internal_ft_deposit_of(&offer.bidder_id, &offer.ft_token_id);
+        self.internal_set_ft_deposit(&offer.bidder_id, &offer.ft_token_id, cur_bal + refund);
+    }
+
+    // views
+
+    //get a collection offer by its ID
+    pub fn get_collection_offer(&self, offer_id: U64) -> Option<CollectionOffer> {
+        self.collection_offers.get(&offer_id.0)
+    }
+
+    //returns paginated collection offers on an nft contract that are paid in an FT, best price first. Expired offers
+    //are left out. If no FT contract is given, the default FT is used. (result is a vector of collection offers)
+    pub fn get_best_collection_offers(
+        &self,
+        nft_contract_id: AccountId,
+        ft_token_id: Option<FungibleTokenId>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<CollectionOffer> {
+        //walk the offers on the nft contract that are paid in the FT, highest price first
+        let ft_token_id = ft_token_id.unwrap_or_else(|| self.ft_id.clone());
+        let from = (nft_contract_id.clone(), ft_token_id.clone(), 0, 0);
+        let to = (nft_contract_id, ft_token_id, u128::MAX, u64::MAX);
+        let now = env::block_timestamp();
+
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        self.collection_offers_by_price
+            .range((Bound::Included(from), Bound::Included(to)))
+            //map the keys into collection offer objects
+            .map(|((_, _, _, offer_id), _)| self.collection_offers.get(&offer_id).unwrap())
+            //leave out the offers that can no longer be filled
+            .filter(|offer| offer.expires_at.0 > now)
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            .collect()
+    }
+
+    //returns paginated collection offers made by a bidder. (result is a vector of collection offers)
+    pub fn get_collection_offers_by_bidder(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<CollectionOffer> {
+        //get the set of collection offers made by the bidder. If there wasn't one, return an empty vector
+        let by_bidder = if let Some(by_bidder) = self.collection_offers_by_bidder.get(&account_id) {
+            by_bidder
+        } else {
+            return vec![];
+        };
+
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        by_bidder.as_vector().iter()
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            //map the offer IDs into collection offer objects
+            .map(|offer_id| self.collection_offers.get(&offer_id).unwrap())
+            .collect()
+    }
+}
+
+
@@ -0,0 +1,257 @@
+//! Standard for NFT market events.
+//!
+//! These events will be picked up by the NEAR indexer so that listings can be followed off-chain.
+//!
+//! This is an extension of the events format (nep-297):
+//! <https://github.com/near/NEPs/blob/master/specs/Standards/EventsFormat.md>
+//!
+//! The events are logged under the custom `nft_market` standard. They are [`SaleCreated`], [`SaleUpdated`],
+//! [`SaleRemoved`], [`SalePurchased`], [`PurchaseFailed`], [`FtDeposit`] and [`FtWithdraw`].
+//!
+//! These events can be logged by calling `.emit()` on them if a single event, or calling
+//! `emit_many` on the event type.
+
+use std::collections::HashMap;
+
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, AccountId};
+
+use crate::sale::{Currency, Sale, SaleConditions};
+use crate::{FungibleTokenId, TokenId};
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "standard")]
+#[must_use = "don't forget to `.emit()` this event"]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NearEvent<'a> {
+    NftMarket(NftMarketEvent<'a>),
+}
+
+impl<'a> NearEvent<'a> {
+    fn to_json_string(&self) -> String {
+        // Events cannot fail to serialize so fine to panic on error
+        #[allow(clippy::redundant_closure)]
+        near_sdk::serde_json::to_string(self).ok().unwrap_or_else(|| env::abort())
+    }
+
+    fn to_json_event_string(&self) -> String {
+        format!("EVENT_JSON:{}", self.to_json_string())
+    }
+
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub(crate) fn emit(self) {
+        near_sdk::env::log_str(&self.to_json_event_string());
+    }
+}
+
+/// Data to log when a token is listed. This is the whole sale object. To log this event,
+/// call [`.emit()`](SaleCreated::emit).
+#[must_use]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleCreated<'a> {
+    #[serde(flatten)]
+    pub sale: &'a Sale,
+}
+
+impl SaleCreated<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a sale created event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`SaleCreated`] represents the data of each listing.
+    pub fn emit_many(data: &[SaleCreated<'_>]) {
+        new_market_v1(NftMarketEventKind::SaleCreated(data)).emit()
+    }
+}
+
+/// Data to log when the price of a sale is updated. To log this event,
+/// call [`.emit()`](SaleUpdated::emit).
+#[must_use]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleUpdated<'a> {
+    pub owner_id: &'a AccountId,
+    pub nft_contract_id: &'a str,
+    pub token_id: &'a TokenId,
+    pub sale_conditions: &'a SaleConditions,
+}
+
+impl SaleUpdated<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a sale updated event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`SaleUpdated`] represents the data of each update.
+    pub fn emit_many(data: &[SaleUpdated<'_>]) {
+        new_market_v1(NftMarketEventKind::SaleUpdated(data)).emit()
+    }
+}
+
+/// Data to log when a sale is taken off the market, whether it was removed by its owner, bought, settled or pruned.
+/// To log this event, call [`.emit()`](SaleRemoved::emit).
+#[must_use]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SaleRemoved<'a> {
+    pub owner_id: &'a AccountId,
+    pub nft_contract_id: &'a str,
+    pub token_id: &'a TokenId,
+}
+
+impl SaleRemoved<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a sale removed event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`SaleRemoved`] represents the data of each removal.
+    pub fn emit_many(data: &[SaleRemoved<'_>]) {
+        new_market_v1(NftMarketEventKind::SaleRemoved(data)).emit()
+    }
+}
+
+/// Data to log when a token is bought and its price is paid out. To log this event,
+/// call [`.emit()`](SalePurchased::emit).
+#[must_use]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SalePurchased<'a> {
+    pub nft_contract_id: &'a AccountId,
+    pub token_id: &'a TokenId,
+    pub seller_id: &'a AccountId,
+    pub buyer_id: &'a AccountId,
+    pub currency: &'a Currency,
+    pub price: &'a U128,
+    pub protocol_fee: &'a U128,
+    pub payout: &'a HashMap<AccountId, U128>,
+}
+
+impl SalePurchased<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a sale purchased event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`SalePurchased`] represents the data of each purchase.
+    pub fn emit_many(data: &[SalePurchased<'_>]) {
+        new_market_v1(NftMarketEventKind::SalePurchased(data)).emit()
+    }
+}
+
+/// Data to log when the token couldn't be transferred to the buyer and they were refunded. To log this event,
+/// call [`.emit()`](PurchaseFailed::emit).
+#[must_use]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurchaseFailed<'a> {
+    pub nft_contract_id: &'a AccountId,
+    pub token_id: &'a TokenId,
+    pub seller_id: &'a AccountId,
+    pub buyer_id: &'a AccountId,
+    pub currency: &'a Currency,
+    pub price: &'a U128,
+}
+
+impl PurchaseFailed<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits a purchase failed event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`PurchaseFailed`] represents the data of each failed purchase.
+    pub fn emit_many(data: &[PurchaseFailed<'_>]) {
+        new_market_v1(NftMarketEventKind::PurchaseFailed(data)).emit()
+    }
+}
+
+/// Data to log when FTs are deposited into the market. To log this event,
+/// call [`.emit()`](FtDeposit::emit).
+#[must_use]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtDeposit<'a> {
+    pub account_id: &'a AccountId,
+    pub ft_token_id: &'a FungibleTokenId,
+    pub amount: &'a U128,
+}
+
+impl FtDeposit<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits an FT deposit event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`FtDeposit`] represents the data of each deposit.
+    pub fn emit_many(data: &[FtDeposit<'_>]) {
+        new_market_v1(NftMarketEventKind::FtDeposit(data)).emit()
+    }
+}
+
+/// Data to log when deposited FTs are withdrawn from the market. To log this event,
+/// call [`.emit()`](FtWithdraw::emit).
+#[must_use]
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtWithdraw<'a> {
+    pub account_id: &'a AccountId,
+    pub ft_token_id: &'a FungibleTokenId,
+    pub amount: &'a U128,
+}
+
+impl FtWithdraw<'_> {
+    /// Logs the event to the host. This is required to ensure that the event is triggered
+    /// and to consume the event.
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    /// Emits an FT withdraw event, through [`env::log_str`](near_sdk::env::log_str),
+    /// where each [`FtWithdraw`] represents the data of each withdrawal.
+    pub fn emit_many(data: &[FtWithdraw<'_>]) {
+        new_market_v1(NftMarketEventKind::FtWithdraw(data)).emit()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub(crate) struct NftMarketEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: NftMarketEventKind<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum NftMarketEventKind<'a> {
+    SaleCreated(&'a [SaleCreated<'a>]),
+    SaleUpdated(&'a [SaleUpdated<'a>]),
+    SaleRemoved(&'a [SaleRemoved<'a>]),
+    SalePurchased(&'a [SalePurchased<'a>]),
+    PurchaseFailed(&'a [PurchaseFailed<'a>]),
+    FtDeposit(&'a [FtDeposit<'a>]),
+    FtWithdraw(&'a [FtWithdraw<'a>]),
+}
+
+fn new_market_v1(event_kind: NftMarketEventKind) -> NearEvent {
+    NearEvent::NftMarket(NftMarketEvent { version: "1.0.0", event_kind })
+}
@@ -2,22 +2,24 @@ use crate::*;
 
 /// external contract calls
 
-//initiate a cross contract call to the nft contract. This will transfer the token to the buyer
+//initiate a cross contract call to the nft contract. This will transfer the token to the buyer and return
+//a payout object used for the market to distribute funds to the appropriate accounts (NEP-199).
 #[ext_contract(ext_nft_contract)]
-trait ExtNftContract {
-    fn nft_transfer(
+pub trait ExtNftContract {
+    fn nft_transfer_payout(
         &mut self,
         receiver_id: AccountId, // purchaser (person to transfer the NFT to)
         token_id: TokenId, // token ID to transfer
         approval_id: Option<u64>, // market contract's approval ID in order to transfer the token on behalf of the owner
         memo: Option<String>, //memo (to include some context)
-    );
+        balance: U128, // the price that the token was purchased for. This will be used in conjunction with the royalty percentages for the token in order to determine how much money should go to which account.
+        max_len_payout: Option<u32>, // the maximum amount of accounts the market can payout at once (this is limited by GAS)
+    ) -> Payout;
 }
 
-//initiate a cross contract call to the nft contract. This will transfer the token to the buyer and return
-//a payout object used for the market to distribute funds to the appropriate accounts.
+//initiate a cross contract call to the ft contract. This is used to pay out the FTs of a sale and to withdraw deposits
 #[ext_contract(ext_ft_contract)]
-trait ExtFtContract {
+pub trait ExtFtContract {
     fn ft_transfer(
         &mut self,
         receiver_id: AccountId, 
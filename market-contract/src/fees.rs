@@ -0,0 +1,129 @@
+use crate::*;
+use near_sdk::{require, PromiseResult};
+
+//the denominator of the protocol fee in basis points
+pub const FEE_BPS_DENOMINATOR: u16 = 10_000;
+
+//the highest protocol fee the owner can set in basis points (10%)
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000;
+
+impl Contract {
+    //internal method for calculating the protocol fee taken from a sale's price (rounded down)
+    pub(crate) fn internal_protocol_fee(&self, price: Balance) -> Balance {
+        price * u128::from(self.protocol_fee_bps) / u128::from(FEE_BPS_DENOMINATOR)
+    }
+
+    //internal method for making sure the predecessor is the owner of the contract
+    pub(crate) fn assert_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "Only the owner can call this method"
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //sets the protocol fee taken from every sale in basis points. Only the owner can call this method.
+    pub fn set_protocol_fee(&mut self, fee_bps: u16) {
+        self.assert_owner();
+        //make sure the fee isn't above the cap
+        require!(
+            fee_bps <= MAX_PROTOCOL_FEE_BPS,
+            format!("The protocol fee can't be more than {} basis points", MAX_PROTOCOL_FEE_BPS)
+        );
+        self.protocol_fee_bps = fee_bps;
+    }
+
+    //withdraws accrued protocol fees of an FT contract to the owner via `ft_transfer`. If no FT contract is given, the
+    //default FT is used. If no amount is given, everything is withdrawn.
+    //the owner MUST be registered on the FT contract. If the transfer fails, the fees are added back.
+    #[payable]
+    pub fn withdraw_protocol_fees(&mut self, ft_token_id: Option<FungibleTokenId>, amount: Option<U128>) {
+        //make sure the user attaches exactly 1 yoctoNEAR for security purposes.
+        //this will redirect them to the NEAR wallet (or requires a full access key). 
+        assert_one_yocto();
+        self.assert_owner();
+
+        //get the amount to withdraw and make sure there are enough fees to cover it
+        let ft_token_id = ft_token_id.unwrap_or_else(|| self.ft_id.clone());
+        let accrued_fees = self.accrued_fees.get(&ft_token_id).unwrap_or(0);
+        let amount = amount.map(|a| a.0).unwrap_or(accrued_fees);
+        require!(amount > 0, "There are no fees to withdraw");
+        require!(amount <= accrued_fees, "Not enough accrued fees");
+
+        //subtract the amount from the accrued fees
+        self.accrued_fees.insert(&ft_token_id, &(accrued_fees - amount));
+
+        //perform the cross contract call to transfer the FTs to the owner. If anything goes wrong
+        //we add the fees back when we resolve the promise
+        ext_ft_contract::ext(ft_token_id.clone())
+            // Attach 1 yoctoNEAR with static GAS equal to the GAS for nft transfer. Also attach an unused GAS weight of 1 by default.
+            .with_attached_deposit(1)
+            .ft_transfer(
+                self.owner_id.clone(), //owner to transfer the fees to
+                U128(amount), //amount to transfer
+                Some("Marketplace protocol fees".to_string()), //memo (to include some context)
+            )
+        .then(
+            // No attached deposit with static GAS equal to the GAS for resolving the refund. Also attach an unused GAS weight of 1 by default.
+            Self::ext(env::current_account_id())
+            .with_static_gas(GAS_FOR_RESOLVE_REFUND)
+            .resolve_fee_withdrawal(ft_token_id, U128(amount))
+        );
+    }
+
+    //withdraws accrued protocol fees in NEAR to the owner. If no amount is given, everything is withdrawn.
+    #[payable]
+    pub fn withdraw_near_protocol_fees(&mut self, amount: Option<U128>) {
+        //make sure the user attaches exactly 1 yoctoNEAR for security purposes.
+        //this will redirect them to the NEAR wallet (or requires a full access key). 
+        assert_one_yocto();
+        self.assert_owner();
+
+        //get the amount to withdraw and make sure there are enough fees to cover it
+        let amount = amount.map(|a| a.0).unwrap_or(self.accrued_near_fees);
+        require!(amount > 0, "There are no fees to withdraw");
+        require!(amount <= self.accrued_near_fees, "Not enough accrued fees");
+
+        //subtract the amount from the accrued fees and transfer it to the owner
+        self.accrued_near_fees -= amount;
+        Promise::new(self.owner_id.clone()).transfer(amount);
+    }
+
+    //private method used to resolve the fee withdrawal. If the transfer failed, the fees are added back.
+    #[private]
+    pub fn resolve_fee_withdrawal(&mut self, ft_token_id: FungibleTokenId, amount: U128) -> U128 {
+        match env::promise_result(0) {
+            PromiseResult::NotReady => env::abort(),
+            // If the transfer went through, there's nothing to revert
+            PromiseResult::Successful(_) => U128(0),
+            // If the transfer failed, add the fees back so they can be withdrawn again
+            PromiseResult::Failed => {
+                let accrued_fees = self.accrued_fees.get(&ft_token_id).unwrap_or(0);
+                self.accrued_fees.insert(&ft_token_id, &(accrued_fees + amount.0));
+                amount
+            }
+        }
+    }
+
+    // views
+    
+    //returns the protocol fee taken from every sale in basis points
+    pub fn get_protocol_fee(&self) -> u16 {
+        self.protocol_fee_bps
+    }
+
+    //returns the protocol fees of an FT contract that were collected and not withdrawn yet. If no FT contract
+    //is given, the default FT is used.
+    pub fn get_accrued_fees(&self, ft_token_id: Option<FungibleTokenId>) -> U128 {
+        let ft_token_id = ft_token_id.unwrap_or_else(|| self.ft_id.clone());
+        U128(self.accrued_fees.get(&ft_token_id).unwrap_or(0))
+    }
+
+    //returns the protocol fees in NEAR that were collected and not withdrawn yet
+    pub fn get_accrued_near_fees(&self) -> U128 {
+        U128(self.accrued_near_fees)
+    }
+}
@@ -2,14 +2,14 @@ use near_sdk::{require, PromiseResult};
 
 use crate::*;
 
-/// transfer callbacks from FT Contracts
+// transfer callbacks from FT Contracts
 
 /*
     trait that will be used as the callback from the FT contract. When ft_transfer_call is
     called, it will fire a cross contract call to this marketplace and this is the function
     that is invoked. 
 */
-trait FungibleTokenReceiver {
+pub trait FungibleTokenReceiver {
     fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
@@ -18,18 +18,21 @@ trait FungibleTokenReceiver {
 
     fn ft_withdraw(
         &mut self,
-        amount: U128
+        amount: U128,
+        ft_token_id: Option<FungibleTokenId>
     );
 
     fn resolve_refund(
         &mut self,
         caller: AccountId,
+        ft_token_id: FungibleTokenId,
         amount: U128
     ) -> U128;
 
     fn ft_deposits_of(
         &self,
-        account_id: AccountId
+        account_id: AccountId,
+        ft_token_id: Option<FungibleTokenId>
     ) -> U128;
 }
 
@@ -44,11 +47,8 @@ impl FungibleTokenReceiver for Contract {
     ) -> U128 {
         // get the contract ID which is the predecessor
         let ft_contract_id = env::predecessor_account_id();
-        // Ensure only the specified FT can be used
-        require!(
-            ft_contract_id == self.ft_id,
-            "FT contract ID does not match"
-        );
+        // Ensure only approved FTs can be used
+        self.assert_approved_ft_token_id(&ft_contract_id);
         
         //get the signer which is the person who initiated the transaction
         let signer_id = env::signer_account_id();
@@ -67,19 +67,27 @@ impl FungibleTokenReceiver for Contract {
             "owner_id should be signer_id"
         );
 
-        // Add the amount to the user's current balance
-        let mut cur_bal = self.ft_deposits.get(&signer_id).unwrap_or(0);
-        cur_bal += amount.0;
-        self.ft_deposits.insert(&signer_id, &cur_bal);
+        // Add the amount to the user's current balance for this FT
+        let cur_bal = self.internal_ft_deposit_of(&signer_id, &ft_contract_id);
+        self.internal_set_ft_deposit(&signer_id, &ft_contract_id, cur_bal + amount.0);
+
+        FtDeposit {
+            account_id: &signer_id,
+            ft_token_id: &ft_contract_id,
+            amount: &amount,
+        }
+        .emit();
 
         // We don't return any FTs to the sender because we're storing all of them in their balance
         U128(0)
     }
 
+    /// Withdraw deposited FTs of a given FT contract. If no FT contract is given, the market's default FT is used
     #[payable]
     fn ft_withdraw(
             &mut self,
-            amount: U128
+            amount: U128,
+            ft_token_id: Option<FungibleTokenId>
     ) {
         //make sure the user attaches exactly 1 yoctoNEAR for security purposes.
         //this will redirect them to the NEAR wallet (or requires a full access key). 
@@ -87,7 +95,8 @@ impl FungibleTokenReceiver for Contract {
 
         // Get the caller and ensure they have enough balance
         let caller = env::predecessor_account_id();
-        let cur_bal = self.ft_deposits.get(&caller).unwrap_or(0);
+        let ft_token_id = ft_token_id.unwrap_or_else(|| self.ft_id.clone());
+        let cur_bal = self.internal_ft_deposit_of(&caller, &ft_token_id);
         require!(
             cur_bal >= amount.0,
             "Insufficient balance"
@@ -95,11 +104,11 @@ impl FungibleTokenReceiver for Contract {
 
         // Subtract the amount from the caller's balance
         let new_bal = cur_bal - amount.0;
-        self.ft_deposits.insert(&caller, &new_bal);
+        self.internal_set_ft_deposit(&caller, &ft_token_id, new_bal);
 
         // Perform the cross contract call to transfer the FTs to the caller. If anything goes wrong
         // We increment their balance back when we resolve the promise
-        ext_ft_contract::ext(self.ft_id.clone())
+        ext_ft_contract::ext(ft_token_id.clone())
             // Attach 1 yoctoNEAR with static GAS equal to the GAS for nft transfer. Also attach an unused GAS weight of 1 by default.
             .with_attached_deposit(1)
             .ft_transfer(
@@ -113,6 +122,7 @@ impl FungibleTokenReceiver for Contract {
             .with_static_gas(GAS_FOR_RESOLVE_REFUND)
             .resolve_refund(
                 caller, //caller to refund the FTs to
+                ft_token_id, //FT contract the FTs were withdrawn from
                 amount, //amount to transfer
             )
         );
@@ -122,6 +132,7 @@ impl FungibleTokenReceiver for Contract {
     fn resolve_refund(
         &mut self,
         caller: AccountId,
+        ft_token_id: FungibleTokenId,
         amount: U128
     ) -> U128 {
         let amount: Balance = amount.into();
@@ -137,22 +148,33 @@ impl FungibleTokenReceiver for Contract {
             PromiseResult::Failed => amount,
         };
 
-        if revert_amount > 0 {
+        if revert_amount == 0 {
+            // The withdrawal went through
+            FtWithdraw {
+                account_id: &caller,
+                ft_token_id: &ft_token_id,
+                amount: &U128(amount),
+            }
+            .emit();
+        } else {
             // Get the caller's current balance
-            let cur_bal = self.ft_deposits.get(&caller).unwrap_or(0);
+            let cur_bal = self.internal_ft_deposit_of(&caller, &ft_token_id);
             // Add the amount to the caller's balance
             let new_bal = cur_bal + revert_amount;
-            self.ft_deposits.insert(&caller, &new_bal);
+            self.internal_set_ft_deposit(&caller, &ft_token_id, new_bal);
         }
 
         U128(revert_amount)
     }
 
-    /// Get the amount of FTs the user has deposited into the contract
+    /// Get the amount of FTs of a given FT contract the user has deposited into the contract. If no FT contract
+    /// is given, the market's default FT is used
     fn ft_deposits_of(
         &self,
-        account_id: AccountId
+        account_id: AccountId,
+        ft_token_id: Option<FungibleTokenId>
     ) -> U128 {
-        self.ft_deposits.get(&account_id).unwrap_or(0).into()
+        let ft_token_id = ft_token_id.unwrap_or_else(|| self.ft_id.clone());
+        self.internal_ft_deposit_of(&account_id, &ft_token_id).into()
     }
 }
\ No newline at end of file
@@ -0,0 +1,75 @@
+use crate::*;
+use near_sdk::require;
+
+impl Contract {
+    //internal method for getting how many FTs of a given FT contract an account has deposited
+    pub(crate) fn internal_ft_deposit_of(&self, account_id: &AccountId, ft_token_id: &FungibleTokenId) -> Balance {
+        self.ft_deposits
+            .get(&(account_id.clone(), ft_token_id.clone()))
+            .or_else(|| {
+                //deposits made before the market supported several FTs are all in the default FT
+                if ft_token_id == &self.ft_id {
+                    self.legacy_ft_deposits.get(account_id)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(0)
+    }
+
+    //internal method for setting how many FTs of a given FT contract an account has deposited.
+    //empty balances are removed from the map to free up storage, and old deposits are moved to the new map
+    pub(crate) fn internal_set_ft_deposit(
+        &mut self,
+        account_id: &AccountId,
+        ft_token_id: &FungibleTokenId,
+        balance: Balance,
+    ) {
+        let key = (account_id.clone(), ft_token_id.clone());
+        if balance == 0 {
+            self.ft_deposits.remove(&key);
+        } else {
+            self.ft_deposits.insert(&key, &balance);
+        }
+        if ft_token_id == &self.ft_id {
+            self.legacy_ft_deposits.remove(account_id);
+        }
+    }
+
+    //internal method for making sure an FT contract is approved for use on the market
+    pub(crate) fn assert_approved_ft_token_id(&self, ft_token_id: &FungibleTokenId) {
+        require!(
+            self.approved_ft_token_ids.contains(ft_token_id),
+            format!("The FT contract {} is not approved", ft_token_id)
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //adds FT contracts that sales can be priced in. Only the owner can call this method.
+    pub fn add_approved_ft_token_ids(&mut self, ft_token_ids: Vec<FungibleTokenId>) {
+        self.assert_owner();
+        for ft_token_id in ft_token_ids {
+            self.approved_ft_token_ids.insert(&ft_token_id);
+        }
+    }
+
+    //removes FT contracts from the approved list. Existing sales and deposits in those FTs can still be
+    //bought and withdrawn, but no new sales or deposits can use them. Only the owner can call this method.
+    pub fn remove_approved_ft_token_ids(&mut self, ft_token_ids: Vec<FungibleTokenId>) {
+        self.assert_owner();
+        for ft_token_id in ft_token_ids {
+            //the default FT must always be approved since sales fall back to it
+            require!(ft_token_id != self.ft_id, "The default FT can't be removed");
+            self.approved_ft_token_ids.remove(&ft_token_id);
+        }
+    }
+
+    // views
+
+    //returns the FT contracts that sales can be priced in
+    pub fn get_approved_ft_token_ids(&self) -> Vec<FungibleTokenId> {
+        self.approved_ft_token_ids.to_vec()
+    }
+}
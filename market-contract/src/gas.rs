@@ -0,0 +1,60 @@
+use crate::*;
+use near_sdk::require;
+
+//the default GAS attached to `nft_transfer_payout` on the NFT contract. Contracts that pay out many royalty
+//recipients need more, so the owner can raise it with `set_gas_settings`
+pub const DEFAULT_GAS_FOR_NFT_TRANSFER: Gas = Gas(25_000_000_000_000);
+
+//the default GAS kept for `resolve_purchase`, which pays out up to MAX_LEN_PAYOUT accounts
+pub const DEFAULT_GAS_FOR_RESOLVE_PURCHASE: Gas = Gas(115_000_000_000_000);
+
+//the GAS kept for every `resolve_payout` callback of an FT payout
+pub const GAS_FOR_RESOLVE_PAYOUT: Gas = Gas(5_000_000_000_000);
+
+//the most GAS a single transaction can attach. The GAS of a purchase can't exceed this
+pub const MAX_PREPAID_GAS: Gas = Gas(300_000_000_000_000);
+
+//the GAS budgets used when a token is bought
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasSettings {
+    //the GAS attached to `nft_transfer_payout` on the NFT contract
+    pub nft_transfer: Gas,
+    //the GAS kept for `resolve_purchase`. It must cover a payout and its callback for MAX_LEN_PAYOUT accounts
+    pub resolve_purchase: Gas,
+}
+
+impl Default for GasSettings {
+    fn default() -> Self {
+        Self {
+            nft_transfer: DEFAULT_GAS_FOR_NFT_TRANSFER,
+            resolve_purchase: DEFAULT_GAS_FOR_RESOLVE_PURCHASE,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    //sets the GAS budgets used when a token is bought. Only the owner can call this method.
+    pub fn set_gas_settings(&mut self, gas_settings: GasSettings) {
+        self.assert_owner();
+        //every payout schedules a resolve_payout callback, so resolve_purchase must at least cover those
+        let min_gas_for_resolve_purchase = GAS_FOR_RESOLVE_PAYOUT.0 * u64::from(MAX_LEN_PAYOUT);
+        require!(
+            gas_settings.resolve_purchase.0 > min_gas_for_resolve_purchase,
+            format!("The GAS for resolve_purchase must be more than {}", min_gas_for_resolve_purchase)
+        );
+        require!(
+            gas_settings.nft_transfer.0 + gas_settings.resolve_purchase.0 < MAX_PREPAID_GAS.0,
+            format!("The GAS for a purchase must be less than {}", MAX_PREPAID_GAS.0)
+        );
+        self.gas_settings = gas_settings;
+    }
+
+    // views
+
+    //returns the GAS budgets used when a token is bought
+    pub fn get_gas_settings(&self) -> GasSettings {
+        self.gas_settings.clone()
+    }
+}
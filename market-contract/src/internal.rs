@@ -21,6 +21,11 @@ impl Contract {
         //get the sale object by removing the unique sale ID. If there was no sale, panic
         let sale = self.sales.remove(&contract_and_token_id).expect("No sale");
 
+        //if the sale expires, remove it from the expiry index
+        if let Some(expires_at) = sale.expires_at {
+            self.sales_by_expiry.remove(&(expires_at.0, contract_and_token_id.clone()));
+        }
+
         //get the set of sales for the sale's owner. If there's no sale, panic. 
         let mut by_owner_id = self.by_owner_id.get(&sale.owner_id).expect("No sale by_owner_id");
         //remove the unique sale ID from the set of sales
@@ -52,6 +57,13 @@ impl Contract {
                 .insert(&nft_contract_id, &by_nft_contract_id);
         }
 
+        SaleRemoved {
+            owner_id: &sale.owner_id,
+            nft_contract_id: &sale.nft_contract_id,
+            token_id: &sale.token_id,
+        }
+        .emit();
+
         //return the sale object
         sale
     }
@@ -1,5 +1,5 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet};
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
@@ -8,22 +8,37 @@ use near_sdk::{
 };
 use std::collections::HashMap;
 
+use crate::auction::*;
+use crate::collection_offers::*;
+use crate::events::*;
 use crate::external::*;
+use crate::gas::*;
 use crate::internal::*;
+use crate::offers::*;
 use crate::sale::*;
 use near_sdk::env::STORAGE_PRICE_PER_BYTE;
 
-mod external;
-mod internal;
-mod ft_balances;
-mod nft_callbacks;
-mod sale;
-mod sale_views;
+pub mod auction;
+pub mod collection_offers;
+pub mod events;
+pub mod external;
+pub mod fees;
+pub mod gas;
+pub mod ft_tokens;
+pub mod internal;
+pub mod ft_balances;
+pub mod migrate;
+pub mod nft_callbacks;
+pub mod offers;
+pub mod prune;
+pub mod sale;
+pub mod sale_views;
 
 //GAS constants to attach to calls
-const GAS_FOR_RESOLVE_PURCHASE: Gas = Gas(115_000_000_000_000);
 const GAS_FOR_RESOLVE_REFUND: Gas = Gas(30_000_000_000_000);
-const GAS_FOR_NFT_TRANSFER: Gas = Gas(15_000_000_000_000);
+
+//the maximum number of accounts that can be paid out for a single sale (this is limited by GAS)
+const MAX_LEN_PAYOUT: u32 = 10;
 
 //the minimum storage to have a sale on the contract.
 const STORAGE_PER_SALE: u128 = 1000 * STORAGE_PRICE_PER_BYTE;
@@ -51,8 +66,11 @@ pub struct Contract {
     //keep track of the owner of the contract
     pub owner_id: AccountId,
 
-    //which fungible token can be used to purchase NFTs
+    //the default fungible token that sales are priced in if the seller doesn't choose one
     pub ft_id: AccountId,
+
+    //which fungible tokens can be used to purchase NFTs
+    pub approved_ft_token_ids: UnorderedSet<FungibleTokenId>,
     
     /*
         to keep track of the sales, we map the ContractAndTokenId to a Sale. 
@@ -70,8 +88,52 @@ pub struct Contract {
     //keep track of the storage that accounts have payed
     pub storage_deposits: LookupMap<AccountId, Balance>,
 
-    //keep track of how many FTs each account has deposited in order to purchase NFTs with
-    pub ft_deposits: LookupMap<AccountId, Balance>,
+    //keep track of how many FTs of each FT contract each account has deposited in order to purchase NFTs with
+    pub ft_deposits: LookupMap<(AccountId, FungibleTokenId), Balance>,
+
+    //the FT deposits of markets deployed before deposits were kept per FT contract. They're all in the default FT
+    //and are moved to ft_deposits the next time they're written
+    pub legacy_ft_deposits: LookupMap<AccountId, Balance>,
+
+    //the fee taken from every sale in basis points
+    pub protocol_fee_bps: u16,
+
+    //the protocol fees that were collected and not withdrawn by the owner yet, for each FT contract
+    pub accrued_fees: LookupMap<FungibleTokenId, Balance>,
+
+    //the protocol fees in NEAR that were collected and not withdrawn by the owner yet
+    pub accrued_near_fees: Balance,
+
+    //keep track of the standing offers on tokens. The key is the unique sale ID (contract + DELIMITER + token ID) and the bidder
+    pub offers: LookupMap<(ContractAndTokenId, AccountId), TokenOffer>,
+
+    //keep track of the bidders with an offer on every token
+    pub offers_by_token: LookupMap<ContractAndTokenId, UnorderedSet<AccountId>>,
+
+    //keep track of the tokens every bidder has an offer on
+    pub offers_by_bidder: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+
+    //keep track of the offers on any token of an nft contract by their ID
+    pub collection_offers: LookupMap<u64, CollectionOffer>,
+
+    //keep track of the collection offers on every nft contract, ordered by nft contract, FT, best price (stored as
+    //u128::MAX - price so that the highest price comes first) and then by ID
+    pub collection_offers_by_price: TreeMap<(AccountId, FungibleTokenId, u128, u64), ()>,
+
+    //keep track of the collection offer IDs for every bidder
+    pub collection_offers_by_bidder: LookupMap<AccountId, UnorderedSet<u64>>,
+
+    //the ID of the next collection offer
+    pub next_collection_offer_id: u64,
+
+    //keep track of the sales that expire, ordered by when they expire and then by the unique sale ID
+    pub sales_by_expiry: TreeMap<(u64, ContractAndTokenId), ()>,
+
+    //the bounty paid out of a seller's storage deposit for every expired sale that's pruned
+    pub prune_bounty: Balance,
+
+    //the GAS budgets used when a token is bought
+    pub gas_settings: GasSettings,
 }
 
 /// Helper structure to for keys of the persistent collections.
@@ -87,6 +149,18 @@ pub enum StorageKey {
     FTTokenIds,
     StorageDeposits,
     FTDeposits,
+    AccruedFees,
+    Offers,
+    OffersByToken,
+    OffersByTokenInner { token_hash: CryptoHash },
+    OffersByBidder,
+    OffersByBidderInner { account_id_hash: CryptoHash },
+    CollectionOffers,
+    CollectionOffersByPrice,
+    CollectionOffersByBidder,
+    CollectionOffersByBidderInner { account_id_hash: CryptoHash },
+    SalesByExpiry,
+    FTDepositsByToken,
 }
 
 #[near_bindgen]
@@ -98,21 +172,46 @@ impl Contract {
     */
     #[init]
     pub fn new(owner_id: AccountId, ft_id: AccountId) -> Self {
-        let this = Self {
+        let mut this = Self {
             //set the owner_id field equal to the passed in owner_id. 
             owner_id,
 
             //set the FT ID equal to the passed in ft_id.
-            ft_id,
+            ft_id: ft_id.clone(),
+            approved_ft_token_ids: UnorderedSet::new(StorageKey::FTTokenIds),
 
             //Storage keys are simply the prefixes used for the collections. This helps avoid data collision
             sales: UnorderedMap::new(StorageKey::Sales),
             by_owner_id: LookupMap::new(StorageKey::ByOwnerId),
             by_nft_contract_id: LookupMap::new(StorageKey::ByNFTContractId),
             storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
-            ft_deposits: LookupMap::new(StorageKey::FTDeposits),
+            ft_deposits: LookupMap::new(StorageKey::FTDepositsByToken),
+            legacy_ft_deposits: LookupMap::new(StorageKey::FTDeposits),
+
+            //the market doesn't take a fee until the owner sets one
+            protocol_fee_bps: 0,
+            accrued_fees: LookupMap::new(StorageKey::AccruedFees),
+            accrued_near_fees: 0,
+
+            offers: LookupMap::new(StorageKey::Offers),
+            offers_by_token: LookupMap::new(StorageKey::OffersByToken),
+            offers_by_bidder: LookupMap::new(StorageKey::OffersByBidder),
+
+            collection_offers: LookupMap::new(StorageKey::CollectionOffers),
+            collection_offers_by_price: TreeMap::new(StorageKey::CollectionOffersByPrice),
+            collection_offers_by_bidder: LookupMap::new(StorageKey::CollectionOffersByBidder),
+            next_collection_offer_id: 0,
+
+            //expired sales can be pruned without a bounty until the owner sets one
+            sales_by_expiry: TreeMap::new(StorageKey::SalesByExpiry),
+            prune_bounty: 0,
+
+            gas_settings: GasSettings::default(),
         };
 
+        //the default FT is always approved
+        this.approved_ft_token_ids.insert(&ft_id);
+
         //return the Contract object
         this
     }
@@ -123,8 +222,6 @@ impl Contract {
     pub fn storage_deposit(&mut self, account_id: Option<AccountId>) {
         //get the account ID to pay for storage for
         let storage_account_id = account_id 
-            //convert the valid account ID into an account ID
-            .map(|a| a.into())
             //if we didn't specify an account ID, we simply use the caller of the function
             .unwrap_or_else(env::predecessor_account_id);
 
@@ -160,11 +257,9 @@ impl Contract {
         //get the amount that the user has by removing them from the map. If they're not in the map, default to 0
         let mut amount = self.storage_deposits.remove(&owner_id).unwrap_or(0);
         
-        //how many sales is that user taking up currently. This returns a set
-        let sales = self.by_owner_id.get(&owner_id);
-        //get the length of that set. 
-        let len = sales.map(|s| s.len()).unwrap_or_default();
-        //how much NEAR is being used up for all the current sales on the account 
+        //how many sales and offers is that user taking up currently
+        let len = self.internal_storage_items_of(&owner_id);
+        //how much NEAR is being used up for all the current sales and offers on the account 
         let diff = u128::from(len) * STORAGE_PER_SALE;
 
         //the excess to withdraw is the total storage paid - storage being used up.
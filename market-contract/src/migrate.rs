@@ -0,0 +1,69 @@
+use crate::*;
+
+//struct that holds a sale as it was stored before sales had currencies, auctions and expiry
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldSale {
+    pub owner_id: AccountId,
+    pub approval_id: u64,
+    pub nft_contract_id: String,
+    pub token_id: String,
+    pub sale_conditions: SalePriceInFTs,
+}
+
+//the state layout of markets deployed before FT deposits were kept per FT contract
+#[derive(BorshDeserialize)]
+pub struct OldContract {
+    pub owner_id: AccountId,
+    pub ft_id: AccountId,
+    pub sales: UnorderedMap<ContractAndTokenId, OldSale>,
+    pub by_owner_id: LookupMap<AccountId, UnorderedSet<ContractAndTokenId>>,
+    pub by_nft_contract_id: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    pub storage_deposits: LookupMap<AccountId, Balance>,
+    pub ft_deposits: LookupMap<AccountId, Balance>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /*
+        upgrades the state of a market deployed before FT deposits were kept per FT contract. This can only be
+        called by the market account itself, right after deploying the new code.
+
+        every sale is rewritten as a fixed price sale in the default FT in this call, so markets with more sales than
+        fit in the gas of a single call must have some of them removed first. The old FT deposits are all in the
+        default FT and stay in the old map until they're next written.
+    */
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old: OldContract = env::state_read().unwrap_or_else(|| env::panic_str("No state to migrate"));
+
+        //start from a fresh market so that every field that was added since is initialized
+        let mut this = Self::new(old.owner_id, old.ft_id);
+
+        //the sales, the views' sets and the storage deposits keep their old prefixes
+        this.sales = UnorderedMap::try_from_slice(&old.sales.try_to_vec().unwrap()).unwrap();
+        this.by_owner_id = old.by_owner_id;
+        this.by_nft_contract_id = old.by_nft_contract_id;
+        this.storage_deposits = old.storage_deposits;
+        //the old FT deposits are keyed by account only, so they're read from their own map
+        this.legacy_ft_deposits = old.ft_deposits;
+
+        //rewrite every sale in the new format. The old value is replaced in place so it's never read as a new sale
+        for (contract_and_token_id, old_sale) in old.sales.iter() {
+            let sale = Sale {
+                owner_id: old_sale.owner_id,
+                approval_id: old_sale.approval_id,
+                nft_contract_id: old_sale.nft_contract_id,
+                token_id: old_sale.token_id,
+                sale_conditions: SaleConditions::FixedPrice(old_sale.sale_conditions),
+                currency: Currency::Ft(this.ft_id.clone()),
+                auction: None,
+                expires_at: None,
+            };
+            this.sales.insert_raw(&contract_and_token_id.try_to_vec().unwrap(), &sale.try_to_vec().unwrap());
+        }
+
+        this
+    }
+}
+
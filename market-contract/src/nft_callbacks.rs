@@ -1,4 +1,5 @@
 use crate::*;
+use near_sdk::require;
 
 /// approval callbacks from NFT Contracts
 
@@ -6,7 +7,26 @@ use crate::*;
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct SaleArgs {
-    pub sale_conditions: SalePriceInFTs,
+    //a fixed price, or the start price, floor price, start time and duration of a dutch auction
+    pub sale_conditions: SaleConditions,
+    //the FT contract to price the sale in. If neither this nor the currency is given, the market's default FT is used
+    pub ft_token_id: Option<FungibleTokenId>,
+    //the currency to price the sale in, either NEAR or an approved FT. Can't be combined with ft_token_id
+    pub currency: Option<Currency>,
+    //auction the token off to the highest bidder instead. The sale conditions must be a fixed price, which is the lowest first bid
+    pub auction: Option<AuctionArgs>,
+    //when the sale can no longer be bought in nanoseconds since the unix epoch. If omitted, the sale never expires
+    pub expires_at: Option<U64>,
+}
+
+//the msg passed to `nft_approve`. It either accepts an offer, fills a collection offer or lists the token for sale
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(untagged)]
+pub enum ApprovalMsg {
+    AcceptOffer { accept_offer: AcceptOfferArgs },
+    AcceptCollectionOffer { accept_collection_offer: AcceptCollectionOfferArgs },
+    Sale(SaleArgs),
 }
 
 /*
@@ -14,7 +34,7 @@ pub struct SaleArgs {
     called, it will fire a cross contract call to this marketplace and this is the function
     that is invoked. 
 */
-trait NonFungibleTokenApprovalsReceiver {
+pub trait NonFungibleTokenApprovalsReceiver {
     fn nft_on_approve(
         &mut self,
         token_id: TokenId,
@@ -28,7 +48,6 @@ trait NonFungibleTokenApprovalsReceiver {
 #[near_bindgen]
 impl NonFungibleTokenApprovalsReceiver for Contract {
     /// where we add the sale because we know nft owner can only call nft_approve
-
     fn nft_on_approve(
         &mut self,
         token_id: TokenId,
@@ -55,14 +74,38 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
             "owner_id should be signer_id"
         );
 
+        //the msg either accepts an offer, fills a collection offer or holds the sale conditions. The market assumes that the user passed
+        //in a proper msg. If they didn't, it panics. 
+        let sale_args = match near_sdk::serde_json::from_str(&msg).expect("Not valid SaleArgs") {
+            ApprovalMsg::AcceptOffer { accept_offer } => {
+                //if the token was listed, the sale is removed since the token is sold to the bidder
+                self.internal_remove_sale_for_offer(&nft_contract_id, &token_id);
+                self.internal_accept_offer(nft_contract_id, token_id, approval_id, owner_id, accept_offer);
+                return;
+            }
+            ApprovalMsg::AcceptCollectionOffer { accept_collection_offer } => {
+                //if the token was listed, the sale is removed since the token is sold to the bidder
+                self.internal_remove_sale_for_offer(&nft_contract_id, &token_id);
+                self.internal_accept_collection_offer(
+                    nft_contract_id,
+                    token_id,
+                    approval_id,
+                    owner_id,
+                    accept_collection_offer,
+                );
+                return;
+            }
+            ApprovalMsg::Sale(sale_args) => sale_args,
+        };
+
         //we need to enforce that the user has enough storage for 1 EXTRA sale.  
 
         //get the storage for a sale. dot 0 converts from U128 to u128
         let storage_amount = self.storage_minimum_balance().0;
         //get the total storage paid by the owner
         let owner_paid_storage = self.storage_deposits.get(&signer_id).unwrap_or(0);
-        //get the storage required which is simply the storage for the number of sales they have + 1 
-        let signer_storage_required = (self.get_supply_by_owner_id(signer_id).0 + 1) as u128 * storage_amount;
+        //get the storage required which is simply the storage for the number of sales and offers they have + 1 
+        let signer_storage_required = (self.internal_storage_items_of(&signer_id) + 1) as u128 * storage_amount;
         
         //make sure that the total paid is >= the required storage
         assert!(
@@ -72,25 +115,66 @@ impl NonFungibleTokenApprovalsReceiver for Contract {
         );
 
         //if all these checks pass we can create the sale conditions object.
-        let SaleArgs { sale_conditions } =
-            //the sale conditions come from the msg field. The market assumes that the user passed
-            //in a proper msg. If they didn't, it panics. 
-            near_sdk::serde_json::from_str(&msg).expect("Not valid SaleArgs");
+        let SaleArgs { sale_conditions, ft_token_id, currency, auction, expires_at } = sale_args;
+
+        //get the currency of the sale. Sales are priced in the market's default FT unless another currency is given
+        let currency = match (currency, ft_token_id) {
+            (Some(_), Some(_)) => env::panic_str("Only one of currency and ft_token_id can be given"),
+            (Some(currency), None) => currency,
+            (None, ft_token_id) => Currency::Ft(ft_token_id.unwrap_or_else(|| self.ft_id.clone())),
+        };
+        //make sure sales priced in FTs use an approved FT
+        if let Currency::Ft(ft_token_id) = &currency {
+            self.assert_approved_ft_token_id(ft_token_id);
+        }
+
+        //bids are locked from the bidders' FT balances, so auctions must be priced in FTs
+        sale_conditions.assert_valid();
+        let auction = auction.map(|auction| {
+            require!(currency != Currency::Near, "Auctions must be priced in FTs");
+            require!(!sale_conditions.is_dutch_auction(), "English auctions must have a fixed starting price");
+            Auction::new(auction)
+        });
+
+        //auctions end with their end time instead of expiring, so that the top bid can always be settled
+        if let Some(expires_at) = expires_at {
+            require!(auction.is_none(), "Auctions can't expire");
+            require!(expires_at.0 > env::block_timestamp(), "The sale must expire in the future");
+        }
 
         //create the unique sale ID which is the contract + DELIMITER + token ID
         let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+
+        //if the token was already listed, the old sale is replaced. Auctions with bids can only be ended with
+        //settle_auction so that the top bidder's funds aren't stuck
+        if let Some(old_sale) = self.sales.get(&contract_and_token_id) {
+            require!(
+                old_sale.auction.is_none_or(|auction| auction.top_bid.is_none()),
+                "Cannot relist an auction that has bids"
+            );
+            //the old expiry is replaced
+            if let Some(expires_at) = old_sale.expires_at {
+                self.sales_by_expiry.remove(&(expires_at.0, contract_and_token_id.clone()));
+            }
+        }
+        if let Some(expires_at) = expires_at {
+            self.sales_by_expiry.insert(&(expires_at.0, contract_and_token_id.clone()), &());
+        }
         
         //insert the key value pair into the sales map. Key is the unique ID. value is the sale object
-        self.sales.insert(
-            &contract_and_token_id,
-            &Sale {
-                owner_id: owner_id.clone(), //owner of the sale / token
-                approval_id, //approval ID for that token that was given to the market
-                nft_contract_id: nft_contract_id.to_string(), //NFT contract the token was minted on
-                token_id: token_id.clone(), //the actual token ID
-                sale_conditions, //the sale conditions 
-           },
-        );
+        let sale = Sale {
+            owner_id: owner_id.clone(), //owner of the sale / token
+            approval_id, //approval ID for that token that was given to the market
+            nft_contract_id: nft_contract_id.to_string(), //NFT contract the token was minted on
+            token_id: token_id.clone(), //the actual token ID
+            sale_conditions, //the sale conditions 
+            currency, //the currency the sale is priced in
+            auction, //the auction state if the token is auctioned off
+            expires_at, //when the sale expires
+        };
+        self.sales.insert(&contract_and_token_id, &sale);
+
+        SaleCreated { sale: &sale }.emit();
 
         //Extra functionality that populates collections necessary for the view calls 
 
@@ -0,0 +1,323 @@
+use crate::*;
+use near_sdk::require;
+
+//struct that holds a standing offer on a token, whether it's listed or not. The amount is locked from the
+//bidder's FT balance until the offer is accepted or cancelled
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenOffer {
+    //the account that made the offer
+    pub bidder_id: AccountId,
+    //nft contract where the token was minted
+    pub nft_contract_id: AccountId,
+    //actual token ID the offer is for
+    pub token_id: TokenId,
+    //the FT contract the offer is paid in
+    pub ft_token_id: FungibleTokenId,
+    //how many FTs are offered for the token
+    pub amount: U128,
+    //when the offer can no longer be accepted in nanoseconds since the unix epoch
+    pub expires_at: U64,
+}
+
+//the args passed in the msg of `nft_approve` to accept an offer instead of listing the token
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AcceptOfferArgs {
+    //the account whose offer is accepted
+    pub bidder_id: AccountId,
+    //the lowest amount the owner accepts. This protects the owner in case the bidder replaces their offer
+    pub amount: U128,
+}
+
+impl Contract {
+    //internal method for getting how many sales and offers (including collection offers) an account has to pay storage for
+    pub(crate) fn internal_storage_items_of(&self, account_id: &AccountId) -> u64 {
+        let sales = self.by_owner_id.get(account_id).map(|s| s.len()).unwrap_or_default();
+        let offers = self.offers_by_bidder.get(account_id).map(|s| s.len()).unwrap_or_default();
+        sales + offers + self.internal_collection_offers_of(account_id)
+    }
+
+    //internal method for removing the sale of a token that's sold through an offer, if it was listed. Auctions with
+    //bids can only be ended with settle_auction so that the top bidder's funds aren't stuck
+    pub(crate) fn internal_remove_sale_for_offer(&mut self, nft_contract_id: &AccountId, token_id: &TokenId) {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        if let Some(sale) = self.sales.get(&contract_and_token_id) {
+            require!(
+                sale.auction.is_none_or(|auction| auction.top_bid.is_none()),
+                "Cannot accept an offer on an auction that has bids"
+            );
+            self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
+        }
+    }
+
+    //internal method for adding an offer to the market. If the bidder already had an offer on the token, it's replaced
+    //and that offer is returned
+    pub(crate) fn internal_add_offer(&mut self, offer: &TokenOffer) -> Option<TokenOffer> {
+        //get the unique sale ID (contract + DELIMITER + token ID) of the token
+        let contract_and_token_id = format!("{}{}{}", offer.nft_contract_id, DELIMETER, offer.token_id);
+
+        //get the offers on the token. If there are none, we create a new empty set
+        let mut by_token = self.offers_by_token.get(&contract_and_token_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::OffersByTokenInner {
+                    //we get a new unique prefix for the collection by hashing the unique sale ID
+                    token_hash: env::sha256_array(contract_and_token_id.as_bytes()),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_token.insert(&offer.bidder_id);
+        self.offers_by_token.insert(&contract_and_token_id, &by_token);
+
+        //get the offers made by the bidder. If there are none, we create a new empty set
+        let mut by_bidder = self.offers_by_bidder.get(&offer.bidder_id).unwrap_or_else(|| {
+            UnorderedSet::new(
+                StorageKey::OffersByBidderInner {
+                    //we get a new unique prefix for the collection by hashing the bidder
+                    account_id_hash: hash_account_id(&offer.bidder_id),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        by_bidder.insert(&contract_and_token_id);
+        self.offers_by_bidder.insert(&offer.bidder_id, &by_bidder);
+
+        self.offers.insert(&(contract_and_token_id, offer.bidder_id.clone()), offer)
+    }
+
+    //internal method for removing an offer from the market. This returns the removed offer object
+    pub(crate) fn internal_remove_offer(
+        &mut self,
+        nft_contract_id: &AccountId,
+        token_id: &TokenId,
+        bidder_id: &AccountId,
+    ) -> TokenOffer {
+        //get the unique sale ID (contract + DELIMITER + token ID) of the token
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        //get the offer object by removing it. If there was no offer, panic
+        let offer = self
+            .offers
+            .remove(&(contract_and_token_id.clone(), bidder_id.clone()))
+            .expect("No offer");
+
+        //remove the bidder from the offers on the token, removing the set if it's now empty
+        let mut by_token = self.offers_by_token.get(&contract_and_token_id).expect("No offer by token");
+        by_token.remove(bidder_id);
+        if by_token.is_empty() {
+            self.offers_by_token.remove(&contract_and_token_id);
+        } else {
+            self.offers_by_token.insert(&contract_and_token_id, &by_token);
+        }
+
+        //remove the token from the offers made by the bidder, removing the set if it's now empty
+        let mut by_bidder = self.offers_by_bidder.get(bidder_id).expect("No offer by bidder");
+        by_bidder.remove(&contract_and_token_id);
+        if by_bidder.is_empty() {
+            self.offers_by_bidder.remove(bidder_id);
+        } else {
+            self.offers_by_bidder.insert(bidder_id, &by_bidder);
+        }
+
+        offer
+    }
+
+    //internal method for giving the locked amount of an offer back to the bidder's FT balance
+    pub(crate) fn internal_refund_offer(&mut self, offer: &TokenOffer) {
+        let cur_bal = self.internal_ft_deposit_of(&offer.bidder_id, &offer.ft_token_id);
+        self.internal_set_ft_deposit(&offer.bidder_id, &offer.ft_token_id, cur_bal + offer.amount.0);
+    }
+
+    //internal method for accepting an offer on behalf of the owner of the token. The offer must not have expired and
+    //must be for at least the amount the owner expects. The token is transferred using the given approval ID.
+    pub(crate) fn internal_accept_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        approval_id: u64,
+        owner_id: AccountId,
+        args: AcceptOfferArgs,
+    ) -> Promise {
+        //remove the offer and make sure it can still be accepted for the expected amount
+        let offer = self.internal_remove_offer(&nft_contract_id, &token_id, &args.bidder_id);
+        require!(env::block_timestamp() < offer.expires_at.0, "The offer has expired");
+        require!(offer.amount.0 >= args.amount.0, "The offer is lower than the expected amount");
+        assert_ne!(owner_id, offer.bidder_id, "Cannot accept your own offer.");
+
+        //the amount was locked from the bidder's balance when the offer was made, so the token can be bought right away
+        self.internal_process_purchase(
+            nft_contract_id,
+            token_id,
+            approval_id,
+            owner_id,
+            Currency::Ft(offer.ft_token_id),
+            offer.amount,
+            offer.bidder_id,
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Make an offer on any token, whether it's listed or not. The amount is locked from your FT balance until the
+    /// offer is accepted or cancelled. Making another offer on the same token replaces the previous one.
+    /// Every offer takes up as much storage as a sale, which must be covered by your storage deposit.
+    #[payable]
+    pub fn make_offer(
+        &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+        ft_token_id: Option<FungibleTokenId>,
+        expires_at: U64,
+    ) {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+
+        //make sure the offer is paid in an approved FT and can be accepted
+        let ft_token_id = ft_token_id.unwrap_or_else(|| self.ft_id.clone());
+        self.assert_approved_ft_token_id(&ft_token_id);
+        require!(amount.0 > 0, "The offer amount must be positive");
+        require!(expires_at.0 > env::block_timestamp(), "The offer must expire in the future");
+
+        //the bidder is the person who called the function
+        let bidder_id = env::predecessor_account_id();
+
+        // get the amount of FTs the bidder has in their balance and lock the offer from it
+        let cur_bal = self.internal_ft_deposit_of(&bidder_id, &ft_token_id);
+        assert!(cur_bal >= amount.0, "Not enough FTs in balance to cover offer: {:?}", amount);
+        self.internal_set_ft_deposit(&bidder_id, &ft_token_id, cur_bal - amount.0);
+
+        //add the offer, giving the bidder back the amount of the offer it replaces
+        let offer = TokenOffer {
+            bidder_id: bidder_id.clone(),
+            nft_contract_id,
+            token_id,
+            ft_token_id,
+            amount,
+            expires_at,
+        };
+        if let Some(previous_offer) = self.internal_add_offer(&offer) {
+            self.internal_refund_offer(&previous_offer);
+        }
+
+        //make sure the bidder has paid enough storage for all their sales and offers
+        let storage_paid = self.storage_deposits.get(&bidder_id).unwrap_or(0);
+        let storage_required = u128::from(self.internal_storage_items_of(&bidder_id)) * STORAGE_PER_SALE;
+        assert!(
+            storage_paid >= storage_required,
+            "Insufficient storage paid: {}, for {} sales and offers at {} rate of per sale",
+            storage_paid, storage_required / STORAGE_PER_SALE, STORAGE_PER_SALE
+        );
+    }
+
+    /// Cancel an offer and get the amount back in the bidder's FT balance. If no bidder is given, your own offer is
+    /// cancelled. Anyone can cancel an offer that has expired.
+    #[payable]
+    pub fn cancel_offer(&mut self, nft_contract_id: AccountId, token_id: TokenId, bidder_id: Option<AccountId>) {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+
+        let caller_id = env::predecessor_account_id();
+        let bidder_id = bidder_id.unwrap_or_else(|| caller_id.clone());
+        let offer = self.internal_remove_offer(&nft_contract_id, &token_id, &bidder_id);
+        require!(
+            caller_id == bidder_id || env::block_timestamp() >= offer.expires_at.0,
+            "Only the bidder can cancel an offer that hasn't expired"
+        );
+
+        self.internal_refund_offer(&offer);
+    }
+
+    /// Accept an offer on a token that's listed on the market. The sale is removed and the token is sold to the bidder
+    /// for the amount of their offer, as long as it's at least `amount`. Unlisted tokens can accept an offer by calling
+    /// `nft_approve` with an `accept_offer` msg.
+    #[payable]
+    pub fn accept_offer(&mut self, nft_contract_id: AccountId, token_id: TokenId, bidder_id: AccountId, amount: U128) {
+        //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
+        assert_one_yocto();
+
+        //remove the sale and make sure the caller owns it
+        let sale = self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
+        assert_eq!(env::predecessor_account_id(), sale.owner_id, "Must be sale owner");
+        //auctions with bids can only be ended with settle_auction so that the top bidder's funds aren't stuck
+        require!(
+            sale.auction.is_none_or(|auction| auction.top_bid.is_none()),
+            "Cannot accept an offer on an auction that has bids"
+        );
+
+        self.internal_accept_offer(
+            nft_contract_id,
+            token_id,
+            sale.approval_id,
+            sale.owner_id,
+            AcceptOfferArgs { bidder_id, amount },
+        );
+    }
+
+    // views
+
+    //get the offer a bidder made on a token
+    pub fn get_offer(&self, nft_contract_id: AccountId, token_id: TokenId, bidder_id: AccountId) -> Option<TokenOffer> {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        self.offers.get(&(contract_and_token_id, bidder_id))
+    }
+
+    //returns paginated offers on a token. (result is a vector of offers)
+    pub fn get_offers_by_token(
+        &self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenOffer> {
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        //get the set of bidders with an offer on the token. If there wasn't one, return an empty vector
+        let by_token = if let Some(by_token) = self.offers_by_token.get(&contract_and_token_id) {
+            by_token
+        } else {
+            return vec![];
+        };
+
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        by_token.as_vector().iter()
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            //map the bidders into offer objects
+            .map(|bidder_id| self.offers.get(&(contract_and_token_id.clone(), bidder_id)).unwrap())
+            .collect()
+    }
+
+    //returns paginated offers made by a bidder. (result is a vector of offers)
+    pub fn get_offers_by_bidder(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<TokenOffer> {
+        //get the set of tokens the bidder made an offer on. If there wasn't one, return an empty vector
+        let by_bidder = if let Some(by_bidder) = self.offers_by_bidder.get(&account_id) {
+            by_bidder
+        } else {
+            return vec![];
+        };
+
+        //where to start pagination - if we have a from_index, we'll use that - otherwise start from 0 index
+        let start = u128::from(from_index.unwrap_or(U128(0)));
+
+        by_bidder.as_vector().iter()
+            //skip to the index we specified in the start variable
+            .skip(start as usize)
+            //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
+            .take(limit.unwrap_or(50) as usize)
+            //map the unique sale IDs into offer objects
+            .map(|contract_and_token_id| self.offers.get(&(contract_and_token_id, account_id.clone())).unwrap())
+            .collect()
+    }
+}
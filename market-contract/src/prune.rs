@@ -0,0 +1,64 @@
+use crate::*;
+use near_sdk::require;
+
+#[near_bindgen]
+impl Contract {
+    //removes up to `limit` expired sales from the market, oldest first. Anyone can call this. The sellers' storage
+    //is freed up so they can withdraw it, minus the prune bounty for every sale which is paid to the caller.
+    //returns how many sales were removed.
+    pub fn prune_sales(&mut self, limit: u64) -> u64 {
+        let now = env::block_timestamp();
+        let mut pruned = 0;
+        let mut bounty = 0;
+
+        while pruned < limit {
+            //get the sale that expires first and stop once it hasn't expired
+            let (expires_at, contract_and_token_id) = match self.sales_by_expiry.min() {
+                Some(key) => key,
+                None => break,
+            };
+            if expires_at > now {
+                break;
+            }
+
+            //remove the sale, which also removes it from the expiry index
+            let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+            self.internal_remove_sale(sale.nft_contract_id.parse().unwrap(), sale.token_id);
+            pruned += 1;
+
+            //take the bounty out of the seller's storage deposit, which still covers their other sales since the
+            //bounty is never more than the storage of a sale
+            let storage_paid = self.storage_deposits.get(&sale.owner_id).unwrap_or(0);
+            let sale_bounty = self.prune_bounty.min(storage_paid);
+            if sale_bounty > 0 {
+                self.storage_deposits.insert(&sale.owner_id, &(storage_paid - sale_bounty));
+                bounty += sale_bounty;
+            }
+        }
+
+        //pay the bounty to the caller
+        if bounty > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(bounty);
+        }
+
+        pruned
+    }
+
+    //sets the bounty paid out of a seller's storage deposit for every expired sale that's pruned. This can't be more
+    //than the storage of a sale. Only the owner can call this method.
+    pub fn set_prune_bounty(&mut self, bounty: U128) {
+        self.assert_owner();
+        require!(
+            bounty.0 <= STORAGE_PER_SALE,
+            format!("The prune bounty can't be more than {}", STORAGE_PER_SALE)
+        );
+        self.prune_bounty = bounty.0;
+    }
+
+    // views
+
+    //returns the bounty paid for every expired sale that's pruned
+    pub fn get_prune_bounty(&self) -> U128 {
+        U128(self.prune_bounty)
+    }
+}
@@ -1,5 +1,73 @@
 use crate::*;
-use near_sdk::{PromiseResult};
+use near_sdk::{require, PromiseResult};
+
+//the currency that a sale is priced and paid in
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Currency {
+    //native NEAR, paid with the deposit attached to `offer_near`
+    Near,
+    //an approved fungible token, paid from the buyer's FT balance on the market
+    Ft(FungibleTokenId),
+}
+
+//the conditions that a sale is priced with. A plain price is a fixed price sale
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(untagged)]
+pub enum SaleConditions {
+    //the token is sold for a fixed price
+    FixedPrice(SalePriceInFTs),
+    //the price falls linearly from the start price to the floor price over the duration (in nanoseconds)
+    DutchAuction {
+        start_price: U128,
+        floor_price: U128,
+        //when the sale opens in nanoseconds since the unix epoch
+        start_time: U64,
+        duration: U64,
+    },
+}
+
+impl SaleConditions {
+    //internal method for making sure the sale conditions can be bought at a sensible price
+    pub(crate) fn assert_valid(&self) {
+        if let SaleConditions::DutchAuction { start_price, floor_price, duration, .. } = self {
+            require!(start_price.0 >= floor_price.0, "The start price must be greater than or equal to the floor price");
+            require!(duration.0 > 0, "The duration must be positive");
+        }
+    }
+
+    //returns the price of the token at a given timestamp in nanoseconds
+    pub(crate) fn price_at(&self, timestamp: u64) -> Balance {
+        match self {
+            SaleConditions::FixedPrice(price) => price.0,
+            SaleConditions::DutchAuction { start_price, floor_price, start_time, duration } => {
+                //the price doesn't fall before the sale opens and stays at the floor once the duration has passed
+                let elapsed = timestamp.saturating_sub(start_time.0);
+                if elapsed >= duration.0 {
+                    return floor_price.0;
+                }
+                //take off `drop * elapsed / duration`, splitting the drop so that the multiplication can't overflow
+                let drop = start_price.0 - floor_price.0;
+                let (duration, elapsed) = (u128::from(duration.0), u128::from(elapsed));
+                start_price.0 - (drop / duration * elapsed + drop % duration * elapsed / duration)
+            }
+        }
+    }
+
+    //returns whether the price falls over time
+    pub(crate) fn is_dutch_auction(&self) -> bool {
+        matches!(self, SaleConditions::DutchAuction { .. })
+    }
+
+    //internal method for making sure a dutch auction has opened
+    pub(crate) fn assert_started(&self) {
+        if let SaleConditions::DutchAuction { start_time, .. } = self {
+            require!(env::block_timestamp() >= start_time.0, "The sale hasn't started yet");
+        }
+    }
+}
 
 //struct that holds important information about each sale on the market
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
@@ -13,8 +81,21 @@ pub struct Sale {
     pub nft_contract_id: String,
     //actual token ID for sale
     pub token_id: String,
-    //sale price in fungible tokens that the token is listed for
-    pub sale_conditions: SalePriceInFTs,
+    //how the token is priced in the sale's currency. Either a fixed price or a dutch auction
+    pub sale_conditions: SaleConditions,
+    //the currency that the sale is priced in
+    pub currency: Currency,
+    //the auction state if the token is auctioned off instead of sold at a fixed price
+    pub auction: Option<Auction>,
+    //when the sale can no longer be bought in nanoseconds since the unix epoch. Expired sales can be pruned by anyone
+    pub expires_at: Option<U64>,
+}
+
+impl Sale {
+    //returns whether the sale has expired
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| env::block_timestamp() >= expires_at.0)
+    }
 }
 
 #[near_bindgen]
@@ -26,11 +107,16 @@ impl Contract {
         //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
         assert_one_yocto();
         //get the sale object as the return value from removing the sale internally
-        let sale = self.internal_remove_sale(nft_contract_id.into(), token_id);
+        let sale = self.internal_remove_sale(nft_contract_id, token_id);
         //get the predecessor of the call and make sure they're the owner of the sale
         let owner_id = env::predecessor_account_id();
         //if this fails, the remove sale will revert
         assert_eq!(owner_id, sale.owner_id, "Must be sale owner");
+        //auctions with bids can only be ended with settle_auction so that the top bidder's funds aren't stuck
+        require!(
+            sale.auction.is_none_or(|auction| auction.top_bid.is_none()),
+            "Cannot remove an auction that has bids"
+        );
     }
 
     //updates the price for a sale on the market
@@ -45,8 +131,7 @@ impl Contract {
         assert_one_yocto();
         
         //create the unique sale ID from the nft contract and token
-        let contract_id: AccountId = nft_contract_id.into();
-        let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
         
         //get the sale object from the unique sale ID. If there is no token, panic. 
         let mut sale = self.sales.get(&contract_and_token_id).expect("No sale");
@@ -57,50 +142,119 @@ impl Contract {
             sale.owner_id,
             "Must be sale owner"
         );
+        //auctions are priced by their bids or over time
+        require!(
+            sale.auction.is_none() && !sale.sale_conditions.is_dutch_auction(),
+            "Cannot update the price of an auction"
+        );
         
         //set the sale conditions equal to the passed in price
-        sale.sale_conditions = price;
+        sale.sale_conditions = SaleConditions::FixedPrice(price);
         //insert the sale back into the map for the unique sale ID
         self.sales.insert(&contract_and_token_id, &sale);
+
+        SaleUpdated {
+            owner_id: &sale.owner_id,
+            nft_contract_id: &sale.nft_contract_id,
+            token_id: &sale.token_id,
+            sale_conditions: &sale.sale_conditions,
+        }
+        .emit();
     }
 
     /// Place an offer on a specific sale. 
     /// The sale will go through as long as you have enough FTs in your balance to cover the amount and the amount is greater than or equal to the sale price
+    /// For dutch auctions, only the current price is paid and the rest of the amount stays in your balance
     #[payable]
     pub fn offer(&mut self, nft_contract_id: AccountId, token_id: String, amount: U128) {
         //assert that the user has attached exactly 1 yoctoNEAR (for security reasons)
         assert_one_yocto();
 
-        //convert the nft_contract_id from a AccountId to an AccountId
-        let contract_id: AccountId = nft_contract_id.into();
         //get the unique sale ID (contract + DELIMITER + token ID)
-        let contract_and_token_id = format!("{}{}{}", contract_id, DELIMETER, token_id);
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
         
         //get the sale object from the unique sale ID. If the sale doesn't exist, panic.
         let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        require!(!sale.is_expired(), "The sale has expired");
+        //auctions are bought by bidding with place_bid
+        require!(sale.auction.is_none(), "This sale is an auction. Use place_bid instead");
         
+        //get the FT contract the sale is priced in. Sales priced in NEAR are bought with offer_near
+        let ft_token_id = match sale.currency {
+            Currency::Ft(ft_token_id) => ft_token_id,
+            Currency::Near => env::panic_str("This sale is priced in NEAR. Use offer_near instead"),
+        };
+
         //get the buyer ID which is the person who called the function and make sure they're not the owner of the sale
         let buyer_id = env::predecessor_account_id();
         assert_ne!(sale.owner_id, buyer_id, "Cannot bid on your own sale.");
         
-        //get the u128 price of the token (dot 0 converts from U128 to u128)
-        let price = sale.sale_conditions.0;
+        //get the current u128 price of the token. Dutch auctions are priced from the block timestamp
+        sale.sale_conditions.assert_started();
+        let price = sale.sale_conditions.price_at(env::block_timestamp());
 
         //make sure the amount offering is greater than or equal to the price of the token
         assert!(amount.0 >= price, "Offer amount must be greater than or eqaul to the price: {:?}", price);
 
-        // get the amount of FTs the buyer has in their balance
-        let cur_bal = self.ft_deposits.get(&buyer_id).unwrap();
+        //dutch auction buyers only pay the current price, which credits the difference back to their balance.
+        //fixed price sales are paid the whole amount offered
+        let paid = if sale.sale_conditions.is_dutch_auction() { price } else { amount.0 };
+
+        // get the amount of FTs the buyer has in their balance for the FT the sale is priced in
+        let cur_bal = self.internal_ft_deposit_of(&buyer_id, &ft_token_id);
         //make sure the buyer has enough FTs to cover the amount they're offering
         assert!(cur_bal >= amount.0, "Not enough FTs in balance to cover offer: {:?}", amount);
-        // if the buyer has enough FTs, subtract the amount from their balance
-        self.ft_deposits.insert(&buyer_id, &(cur_bal - amount.0));
+        // if the buyer has enough FTs, subtract what's paid from their balance
+        self.internal_set_ft_deposit(&buyer_id, &ft_token_id, cur_bal - paid);
 
         //process the purchase (which will remove the sale from the market and perform the transfer)
         self.process_purchase(
-            contract_id,
+            nft_contract_id,
             token_id,
-            amount,
+            U128(paid),
+            buyer_id,
+        );
+    }
+
+    /// Place an offer on a specific sale that's priced in NEAR. 
+    /// The sale will go through as long as the attached deposit is greater than or equal to the sale price. The whole deposit is paid,
+    /// except for dutch auctions where only the current price is paid and the rest of the deposit is refunded.
+    #[payable]
+    pub fn offer_near(&mut self, nft_contract_id: AccountId, token_id: String) {
+        //get the unique sale ID (contract + DELIMITER + token ID)
+        let contract_and_token_id = format!("{}{}{}", nft_contract_id, DELIMETER, token_id);
+        
+        //get the sale object from the unique sale ID. If the sale doesn't exist, panic.
+        let sale = self.sales.get(&contract_and_token_id).expect("No sale");
+        //make sure the sale is priced in NEAR. Sales priced in FTs are bought with offer
+        require!(!sale.is_expired(), "The sale has expired");
+        require!(sale.currency == Currency::Near, "This sale is priced in FTs. Use offer instead");
+        //auctions are bought by bidding with place_bid
+        require!(sale.auction.is_none(), "This sale is an auction. Use place_bid instead");
+        
+        //get the buyer ID which is the person who called the function and make sure they're not the owner of the sale
+        let buyer_id = env::predecessor_account_id();
+        assert_ne!(sale.owner_id, buyer_id, "Cannot bid on your own sale.");
+        
+        //get the current u128 price of the token. Dutch auctions are priced from the block timestamp
+        sale.sale_conditions.assert_started();
+        let price = sale.sale_conditions.price_at(env::block_timestamp());
+
+        //the attached deposit is the payment. Make sure it's greater than or equal to the price of the token
+        let deposit = env::attached_deposit();
+        assert!(deposit >= price, "Attached deposit must be greater than or equal to the current price: {:?}", price);
+
+        //dutch auction buyers only pay the current price and get the rest of their deposit back
+        let paid = if sale.sale_conditions.is_dutch_auction() { price } else { deposit };
+        if deposit > paid {
+            Promise::new(buyer_id.clone()).transfer(deposit - paid);
+        }
+
+        //process the purchase (which will remove the sale from the market and perform the transfer)
+        self.process_purchase(
+            nft_contract_id,
+            token_id,
+            U128(paid),
             buyer_id,
         );
     }
@@ -118,90 +272,314 @@ impl Contract {
         //get the sale object by removing the sale
         let sale = self.internal_remove_sale(nft_contract_id.clone(), token_id.clone());
 
-        //initiate a cross contract call to the nft contract. This will transfer the token to the buyer
-        ext_nft_contract::ext(nft_contract_id)
-            // Attach 1 yoctoNEAR with static GAS equal to the GAS for nft transfer. Also attach an unused GAS weight of 1 by default.
-            .with_attached_deposit(1)
-            .with_static_gas(GAS_FOR_NFT_TRANSFER)
-            .nft_transfer(
-                buyer_id.clone(), //purchaser (person to transfer the NFT to)
-                token_id, //token ID to transfer
-                Some(sale.approval_id), //market contract's approval ID in order to transfer the token on behalf of the owner
-                Some("payout from market".to_string()) //memo (to include some context)
-            )
-        //after the transfer payout has been initiated, we resolve the promise by calling our own resolve_purchase function. 
-        //resolve purchase will send the FTs to the owner of the sale if everything went well.
-        .then(
-            // No attached deposit with static GAS equal to the GAS for resolving the purchase. Also attach an unused GAS weight of 1 by default.
-            Self::ext(env::current_account_id())
-            .with_static_gas(GAS_FOR_RESOLVE_PURCHASE)
-            .resolve_purchase(
-                sale.owner_id, //the seller of the token
-                buyer_id, //the buyer and price are passed in incase something goes wrong and we need to refund the buyer
-                amount,
-            )
+        //transfer the token to the buyer and pay the seller and royalty recipients
+        self.internal_process_purchase(
+            nft_contract_id,
+            token_id,
+            sale.approval_id,
+            sale.owner_id,
+            sale.currency,
+            amount,
+            buyer_id,
         )
     }
 
     /*
-        private method used to resolve the promise when calling nft_transfer_payout. This will
-        transfer the tokens to the owner of the sale if the transfer was successful. If not, the buyer will be refunded.
-        IMPORTANT - the seller MUST be registered on the FT contract before this function is called or else they will NOT
-        receive their FTs
+        private method used to resolve the promise when calling nft_transfer_payout. This will take the payout object and 
+        check to see if it's authentic and there's no problems. If everything is fine, it will keep the protocol fee and
+        pay the accounts the rest of the price via `ft_transfer` or, for sales priced in NEAR, a NEAR transfer.
+        If the payout is malformed, the seller is paid the rest of the price instead. If the transfer failed, the buyer is refunded.
+        for sales priced in FTs, a payout that the FT contract rejects (for example because the account isn't registered
+        on it) is credited to the account's FT deposits on the market instead, where it can be withdrawn with ft_withdraw
     */
     #[private]
+    #[allow(clippy::too_many_arguments)]
     pub fn resolve_purchase(
         &mut self,
+        nft_contract_id: AccountId,
+        token_id: TokenId,
         seller_id: AccountId,
         buyer_id: AccountId,
+        currency: Currency,
         price: U128,
+        protocol_fee: U128,
     ) -> U128 {
-        let amount: Balance = price.into();
+        // the rest of the price after the protocol fee is what the payout must add up to
+        let payout_balance = U128(price.0 - protocol_fee.0);
 
-        // Get the amount to revert the caller's balance with
-        let transfer_amount = match env::promise_result(0) {
+        // checking for payout information returned from the nft_transfer_payout method
+        let payout_option = match env::promise_result(0) {
             PromiseResult::NotReady => env::abort(),
-            // If the promise was successful, we'll transfer all the FTs
-            PromiseResult::Successful(_) => {
-                amount
+            // If the transfer went through, the token now belongs to the buyer so someone has to be paid
+            PromiseResult::Successful(value) => {
+                // Use the payout if it's valid. Otherwise pay the rest of the price to the seller.
+                Some(
+                    near_sdk::serde_json::from_slice::<Payout>(&value)
+                        .ok()
+                        .and_then(|payout| self.internal_validate_payout(payout, payout_balance.0))
+                        .unwrap_or_else(|| HashMap::from([(seller_id.clone(), payout_balance)])),
+                )
             }
-            // If the promise wasn't successful, we won't transfer any FTs and instead refund the buyer
-            PromiseResult::Failed => 0,
+            // If the transfer failed, nobody is paid and the buyer is refunded
+            PromiseResult::Failed => None,
         };
 
-        // If the promise was successful, we'll transfer all the FTs
-        if transfer_amount > 0 {
-            // Perform the cross contract call to transfer the FTs to the seller
-            ext_ft_contract::ext(self.ft_id.clone())
-                // Attach 1 yoctoNEAR with static GAS equal to the GAS for nft transfer. Also attach an unused GAS weight of 1 by default.
-                .with_attached_deposit(1)
-                .ft_transfer(
-                    seller_id, //seller to transfer the FTs to
-                    U128(transfer_amount), //amount to transfer
-                    Some("Sale from marketplace".to_string()), //memo (to include some context)
-                );
-            return U128(transfer_amount);
-        // If the promise was not successful, we won't transfer any FTs and instead refund the buyer
+        let payout = if let Some(payout) = payout_option {
+            payout
+        // If the transfer failed, we refund the buyer
         } else {
-            // Get the buyer's current balance and increment it
-            let cur_bal = self.ft_deposits.get(&buyer_id).unwrap();
-            self.ft_deposits.insert(&buyer_id, &(cur_bal + amount));
+            PurchaseFailed {
+                nft_contract_id: &nft_contract_id,
+                token_id: &token_id,
+                seller_id: &seller_id,
+                buyer_id: &buyer_id,
+                currency: &currency,
+                price: &price,
+            }
+            .emit();
+
+            match &currency {
+                // Send the attached NEAR back to the buyer
+                Currency::Near => {
+                    Promise::new(buyer_id).transfer(price.0);
+                }
+                // Get the buyer's current FT balance and increment it
+                Currency::Ft(ft_token_id) => {
+                    let cur_bal = self.internal_ft_deposit_of(&buyer_id, ft_token_id);
+                    self.internal_set_ft_deposit(&buyer_id, ft_token_id, cur_bal + price.0);
+                }
+            }
             return U128(0);
+        };
+
+        SalePurchased {
+            nft_contract_id: &nft_contract_id,
+            token_id: &token_id,
+            seller_id: &seller_id,
+            buyer_id: &buyer_id,
+            currency: &currency,
+            price: &price,
+            protocol_fee: &protocol_fee,
+            payout: &payout,
+        }
+        .emit();
+
+        // Keep the protocol fee for the owner to withdraw
+        match &currency {
+            Currency::Near => self.accrued_near_fees += protocol_fee.0,
+            Currency::Ft(ft_token_id) => {
+                let accrued_fees = self.accrued_fees.get(ft_token_id).unwrap_or(0);
+                self.accrued_fees.insert(ft_token_id, &(accrued_fees + protocol_fee.0));
+            }
+        }
+
+        // Pay each account its share of the rest of the price
+        for (receiver_id, amount) in payout {
+            // Skip empty shares since the FT contract rejects transfers of 0 tokens
+            if amount.0 == 0 {
+                continue;
+            }
+            match &currency {
+                // Transfer the NEAR to the account
+                Currency::Near => {
+                    Promise::new(receiver_id).transfer(amount.0);
+                }
+                // Perform the cross contract call to transfer the FTs to the account. If it fails, the FTs are
+                // credited to the account's deposits when we resolve the promise
+                Currency::Ft(ft_token_id) => {
+                    ext_ft_contract::ext(ft_token_id.clone())
+                        // Attach 1 yoctoNEAR. Also attach an unused GAS weight of 1 by default.
+                        .with_attached_deposit(1)
+                        .ft_transfer(
+                            receiver_id.clone(), //account to transfer the FTs to
+                            amount, //amount to transfer
+                            Some("Sale from marketplace".to_string()), //memo (to include some context)
+                        )
+                    .then(
+                        // No attached deposit with static GAS equal to the GAS for resolving the payout. Also attach an unused GAS weight of 1 by default.
+                        Self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_PAYOUT)
+                        .resolve_payout(receiver_id, ft_token_id.clone(), amount)
+                    );
+                }
+            }
+        }
+
+        // Return the price that was paid out
+        price
+    }
+
+    //private method used to resolve an FT payout of a purchase. If the transfer failed, the FTs are credited to the
+    //account's FT deposits so that they can be withdrawn once the account is registered on the FT contract
+    #[private]
+    pub fn resolve_payout(&mut self, receiver_id: AccountId, ft_token_id: FungibleTokenId, amount: U128) -> U128 {
+        match env::promise_result(0) {
+            PromiseResult::NotReady => env::abort(),
+            // If the transfer went through, there's nothing to credit
+            PromiseResult::Successful(_) => U128(0),
+            // If the transfer failed, credit the account's FT deposits
+            PromiseResult::Failed => {
+                let cur_bal = self.internal_ft_deposit_of(&receiver_id, &ft_token_id);
+                self.internal_set_ft_deposit(&receiver_id, &ft_token_id, cur_bal + amount.0);
+                amount
+            }
         }
     }
 }
 
-//this is the cross contract call that we call on our own contract. 
-/*
-    private method used to resolve the promise when calling nft_transfer_payout. This will take the payout object and 
-    check to see if it's authentic and there's no problems. If everything is fine, it will pay the accounts. If there's a problem,
-    it will refund the buyer for the price. 
-*/
-#[ext_contract(ext_self)]
-trait ExtSelf {
-    fn resolve_purchase(
+impl Contract {
+    //internal method used when a token is bought, from a sale or an accepted offer. The buyer's payment must already
+    //be taken. This will transfer and get the payout from the nft contract, and then distribute royalties
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn internal_process_purchase(
         &mut self,
+        nft_contract_id: AccountId,
+        token_id: String,
+        approval_id: u64,
+        seller_id: AccountId,
+        currency: Currency,
+        amount: U128,
         buyer_id: AccountId,
-        price: U128,
-    ) -> Promise;
+    ) -> Promise {
+        //take the protocol fee out of the price. The rest is paid out to the seller and royalty recipients
+        let protocol_fee = self.internal_protocol_fee(amount.0);
+        let payout_balance = U128(amount.0 - protocol_fee);
+
+        //initiate a cross contract call to the nft contract. This will transfer the token to the buyer and return
+        //a payout object used for the market to distribute funds to the appropriate accounts.
+        ext_nft_contract::ext(nft_contract_id.clone())
+            // Attach 1 yoctoNEAR with static GAS equal to the GAS for nft transfer. Also attach an unused GAS weight of 1 by default.
+            .with_attached_deposit(1)
+            .with_static_gas(self.gas_settings.nft_transfer)
+            .nft_transfer_payout(
+                buyer_id.clone(), //purchaser (person to transfer the NFT to)
+                token_id.clone(), //token ID to transfer
+                Some(approval_id), //market contract's approval ID in order to transfer the token on behalf of the owner
+                Some("payout from market".to_string()), //memo (to include some context)
+                /*
+                    the price that the token was purchased for minus the protocol fee. This will be used in conjunction with
+                    the royalty percentages for the token in order to determine how much money should go to which account. 
+                */
+                payout_balance,
+                Some(MAX_LEN_PAYOUT), //the maximum amount of accounts the market can payout at once (this is limited by GAS)
+            )
+        //after the transfer payout has been initiated, we resolve the promise by calling our own resolve_purchase function. 
+        //resolve purchase will take the payout object returned from the nft_transfer_payout and actually pay the accounts
+        .then(
+            // No attached deposit with static GAS equal to the GAS for resolving the purchase. Also attach an unused GAS weight of 1 by default.
+            Self::ext(env::current_account_id())
+            .with_static_gas(self.gas_settings.resolve_purchase)
+            .resolve_purchase(
+                nft_contract_id, //the nft contract and token ID are passed in so that the purchase can be logged
+                token_id,
+                seller_id, //the seller of the token
+                buyer_id, //the buyer and price are passed in incase something goes wrong and we need to refund the buyer
+                currency, //the currency that the sale is paid in
+                amount,
+                U128(protocol_fee), //the protocol fee that's kept by the market if the purchase goes through
+            )
+        )
+    }
+
+    //internal method for checking that a payout returned by the NFT contract is valid. The payout must not be
+    //longer than MAX_LEN_PAYOUT and its amounts must add up to exactly the price. Returns None if it's malformed.
+    pub(crate) fn internal_validate_payout(
+        &self,
+        payout: Payout,
+        price: Balance,
+    ) -> Option<HashMap<AccountId, U128>> {
+        //make sure the payout isn't too long to pay out with the attached GAS
+        if payout.payout.len() > MAX_LEN_PAYOUT as usize {
+            return None;
+        }
+
+        //add up the amounts in the payout, making sure the sum doesn't overflow
+        let mut total: Balance = 0;
+        for amount in payout.payout.values() {
+            total = total.checked_add(amount.0)?;
+        }
+
+        //the payout is only valid if it adds up to exactly the price
+        if total == price {
+            Some(payout.payout)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a dutch auction that falls from 1,000 to 400 over 600 nanoseconds, opening at 100
+    fn dutch_auction() -> SaleConditions {
+        SaleConditions::DutchAuction {
+            start_price: U128(1_000),
+            floor_price: U128(400),
+            start_time: U64(100),
+            duration: U64(600),
+        }
+    }
+
+    #[test]
+    fn dutch_price_falls_linearly_to_the_floor() {
+        let conditions = dutch_auction();
+        //the price doesn't fall before the sale opens
+        assert_eq!(conditions.price_at(0), 1_000);
+        assert_eq!(conditions.price_at(100), 1_000);
+        //1 per nanosecond
+        assert_eq!(conditions.price_at(101), 999);
+        assert_eq!(conditions.price_at(400), 700);
+        assert_eq!(conditions.price_at(699), 401);
+        //the price stays at the floor once the duration has passed
+        assert_eq!(conditions.price_at(700), 400);
+        assert_eq!(conditions.price_at(u64::MAX), 400);
+    }
+
+    #[test]
+    fn dutch_price_rounds_in_favor_of_the_seller() {
+        let conditions = SaleConditions::DutchAuction {
+            start_price: U128(10),
+            floor_price: U128(0),
+            start_time: U64(0),
+            duration: U64(3),
+        };
+        //a third of the drop is 3.33, so the price only falls by 3
+        assert_eq!(conditions.price_at(1), 7);
+        assert_eq!(conditions.price_at(2), 4);
+        assert_eq!(conditions.price_at(3), 0);
+    }
+
+    #[test]
+    fn dutch_price_does_not_overflow() {
+        let conditions = SaleConditions::DutchAuction {
+            start_price: U128(u128::MAX),
+            floor_price: U128(0),
+            start_time: U64(0),
+            duration: U64(u64::MAX),
+        };
+        assert_eq!(conditions.price_at(u64::MAX / 2), u128::MAX - u128::MAX / u128::from(u64::MAX) * u128::from(u64::MAX / 2));
+        assert_eq!(conditions.price_at(u64::MAX), 0);
+    }
+
+    #[test]
+    fn fixed_price_does_not_change() {
+        let conditions = SaleConditions::FixedPrice(U128(500));
+        assert_eq!(conditions.price_at(0), 500);
+        assert_eq!(conditions.price_at(u64::MAX), 500);
+        assert!(!conditions.is_dutch_auction());
+    }
+
+    #[test]
+    #[should_panic(expected = "The start price must be greater than or equal to the floor price")]
+    fn dutch_auction_cannot_rise() {
+        SaleConditions::DutchAuction {
+            start_price: U128(400),
+            floor_price: U128(1_000),
+            start_time: U64(0),
+            duration: U64(600),
+        }
+        .assert_valid();
+    }
 }
@@ -2,9 +2,9 @@ use crate::*;
 
 #[near_bindgen]
 impl Contract {
-    /// views
+    // views
     
-    //returns the number of sales the marketplace has up (as a string)
+    //returns the number of sales the marketplace has up (as a string). This includes expired sales until they're pruned
     pub fn get_supply_sales(
         &self,
     ) -> U64 {
@@ -12,7 +12,7 @@ impl Contract {
         U64(self.sales.len())
     }
     
-    //returns the number of sales for a given account (result is a string)
+    //returns the number of sales for a given account (result is a string). This includes expired sales until they're pruned
     pub fn get_supply_by_owner_id(
         &self,
         account_id: AccountId,
@@ -52,17 +52,19 @@ impl Contract {
         
         //iterate through the keys vector
         keys.iter()
+            //we'll map the token IDs which are strings into Sale objects
+            .map(|token_id| self.sales.get(&token_id).unwrap())
+            //hide the sales that have expired before paginating so that pages aren't cut short
+            .filter(|sale| !sale.is_expired())
             //skip to the index we specified in the start variable
             .skip(start as usize) 
             //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
             .take(limit.unwrap_or(50) as usize) 
-            //we'll map the token IDs which are strings into Sale objects
-            .map(|token_id| self.sales.get(&token_id).unwrap())
             //since we turned the keys into an iterator, we need to turn it back into a vector to return
             .collect()
     }
 
-    //get the number of sales for an nft contract. (returns a string)
+    //get the number of sales for an nft contract. (returns a string). This includes expired sales until they're pruned
     pub fn get_supply_by_nft_contract_id(
         &self,
         nft_contract_id: AccountId,
@@ -103,12 +105,14 @@ impl Contract {
         
         //iterate through the keys vector
         keys.iter()
+            //we'll map the token IDs which are strings into Sale objects by passing in the unique sale ID (contract + DELIMITER + token ID)
+            .map(|token_id| self.sales.get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id)).unwrap())
+            //hide the sales that have expired before paginating so that pages aren't cut short
+            .filter(|sale| !sale.is_expired())
             //skip to the index we specified in the start variable
             .skip(start as usize) 
             //take the first "limit" elements in the vector. If we didn't specify a limit, use 50
             .take(limit.unwrap_or(50) as usize) 
-            //we'll map the token IDs which are strings into Sale objects by passing in the unique sale ID (contract + DELIMITER + token ID)
-            .map(|token_id| self.sales.get(&format!("{}{}{}", nft_contract_id, DELIMETER, token_id)).unwrap())
             //since we turned the keys into an iterator, we need to turn it back into a vector to return
             .collect()
     }
@@ -116,7 +120,19 @@ impl Contract {
     //get a sale information for a given unique sale ID (contract + DELIMITER + token ID)
     pub fn get_sale(&self, nft_contract_token: ContractAndTokenId) -> Option<Sale> {
         //try and get the sale object for the given unique sale ID. Will return an option since
-        //we're not guaranteed that the unique sale ID passed in will be valid.
-        self.sales.get(&nft_contract_token)
+        //we're not guaranteed that the unique sale ID passed in will be valid. Expired sales are hidden.
+        self.sales.get(&nft_contract_token).filter(|sale| !sale.is_expired())
+    }
+
+    //get the current price of a sale for a given unique sale ID (contract + DELIMITER + token ID). Dutch auctions
+    //are priced at the current block timestamp and auctions with bids at their top bid. Returns None if there's no
+    //sale or it has expired.
+    pub fn get_sale_price(&self, nft_contract_token: ContractAndTokenId) -> Option<U128> {
+        self.get_sale(nft_contract_token).map(|sale| {
+            match sale.auction.and_then(|auction| auction.top_bid) {
+                Some(top_bid) => top_bid.amount,
+                None => U128(sale.sale_conditions.price_at(env::block_timestamp())),
+            }
+        })
     }
 }